@@ -4,5 +4,34 @@ use anchor_lang::prelude::*;
 pub enum VaultError {
     CalculationFailure,
     SlippageExceeded,
-    ArithmeticOverflow
-}
\ No newline at end of file
+    ArithmeticOverflow,
+    InvalidPriceFeed,
+    StalePrice,
+    PriceConfidenceTooWide,
+    MissingPriceFeed,
+    TickDeviationTooHigh,
+    MissingObservationState,
+    InsufficientObservations,
+    ObservationTooRecent,
+    PriceDeviationTooHigh,
+    FeeTooHigh,
+    MissingFeeRecipient,
+    CompoundTooSoon,
+    InvalidRewardAccount,
+    InvalidTickRange,
+    PositionTooWide,
+    InvalidRebalancePolicy,
+    RebalanceNotDue,
+    InvalidSlippage,
+    InsufficientIdleBalance,
+    DepositTooSmall,
+    DeadlineExceeded,
+    VaultPaused,
+    NoPendingAdmin,
+    TimelockActive,
+    InvalidTimelockDelay,
+    ChangeNotReady,
+    MissingPosition,
+    PositionAlreadyOpen,
+    ProportionalDepositsOnly,
+}
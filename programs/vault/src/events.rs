@@ -0,0 +1,39 @@
+use anchor_lang::prelude::*;
+
+use crate::state::ParameterChange;
+
+#[event]
+pub struct AdminProposed {
+    pub vault: Pubkey,
+    pub admin: Pubkey,
+    pub pending_admin: Pubkey,
+}
+
+#[event]
+pub struct AdminAccepted {
+    pub vault: Pubkey,
+    pub previous_admin: Pubkey,
+    pub admin: Pubkey,
+}
+
+#[event]
+pub struct ChangeScheduled {
+    pub vault: Pubkey,
+    pub id: u64,
+    pub change: ParameterChange,
+    pub executable_at: i64,
+}
+
+#[event]
+pub struct ChangeExecuted {
+    pub vault: Pubkey,
+    pub id: u64,
+    pub change: ParameterChange,
+}
+
+#[event]
+pub struct ChangeCancelled {
+    pub vault: Pubkey,
+    pub id: u64,
+    pub change: ParameterChange,
+}
@@ -0,0 +1,39 @@
+use anchor_lang::prelude::*;
+use whirlpool_cpi::state::Whirlpool;
+use crate::{errors::VaultError, events::AdminAccepted, state::Vault};
+
+#[derive(Accounts)]
+pub struct AcceptAdmin<'info> {
+    #[account(mut, constraint = pending_admin.key() == vault.pending_admin)]
+    pub pending_admin: Signer<'info>,
+    
+    pub whirlpool: Box<Account<'info, Whirlpool>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"vault".as_ref(),
+            whirlpool.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub vault: Box<Account<'info, Vault>>,
+}
+
+pub fn accept_admin(
+    ctx: Context<AcceptAdmin>,
+) -> Result<()> {
+    let vault = &mut ctx.accounts.vault;
+    require!(vault.pending_admin != Pubkey::default(), VaultError::NoPendingAdmin);
+
+    let previous_admin = vault.admin;
+    vault.admin = vault.pending_admin;
+    vault.pending_admin = Pubkey::default();
+
+    emit!(AdminAccepted {
+        vault: vault.key(),
+        previous_admin,
+        admin: vault.admin,
+    });
+    Ok(())
+}
@@ -6,8 +6,8 @@ use crate::state::Vault;
 
 #[derive(Accounts)]
 pub struct AddLiquidity<'info> {
-  #[account(mut, constraint = admin.key() == vault.admin)]
-  pub admin: Signer<'info>,
+  #[account(mut, constraint = operator.key() == vault.operator)]
+  pub operator: Signer<'info>,
   
   pub whirlpool_program: Program<'info, WhirlpoolProgram>,
 
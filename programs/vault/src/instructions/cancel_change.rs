@@ -0,0 +1,38 @@
+use anchor_lang::prelude::*;
+use whirlpool_cpi::state::Whirlpool;
+use crate::{
+    events::ChangeCancelled,
+    state::{PendingChange, Vault},
+};
+
+#[derive(Accounts)]
+pub struct CancelChange<'info> {
+    #[account(mut, constraint = admin.key() == vault.admin)]
+    pub admin: Signer<'info>,
+    
+    pub whirlpool: Box<Account<'info, Whirlpool>>,
+
+    #[account(
+        seeds = [
+            b"vault".as_ref(),
+            whirlpool.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub vault: Box<Account<'info, Vault>>,
+
+    #[account(mut, has_one = vault, close = admin)]
+    pub pending_change: Box<Account<'info, PendingChange>>,
+}
+
+pub fn cancel_change(
+    ctx: Context<CancelChange>,
+) -> Result<()> {
+    let pending_change = &ctx.accounts.pending_change;
+    emit!(ChangeCancelled {
+        vault: ctx.accounts.vault.key(),
+        id: pending_change.id,
+        change: pending_change.change,
+    });
+    Ok(())
+}
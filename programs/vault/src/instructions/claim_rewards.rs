@@ -0,0 +1,108 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Token, TokenAccount, Transfer};
+use whirlpool_cpi::state::Whirlpool;
+use crate::{
+    errors::VaultError,
+    state::{RewardState, UserRewardCheckpoint, Vault, NUM_REWARDS},
+};
+
+#[derive(Accounts)]
+pub struct ClaimRewards<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(constraint = whirlpool.key() == vault.whirlpool)]
+    pub whirlpool: Box<Account<'info, Whirlpool>>,
+
+    #[account(
+        seeds = [
+            b"vault".as_ref(),
+            whirlpool.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub vault: Box<Account<'info, Vault>>,
+
+    #[account(
+        constraint = user_share.mint == vault.share_mint,
+        constraint = user_share.owner == user.key(),
+    )]
+    pub user_share: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"reward_state".as_ref(),
+            whirlpool.key().as_ref(),
+        ],
+        bump,
+        has_one = vault,
+    )]
+    pub reward_state: Box<Account<'info, RewardState>>,
+    // Created on first use, so holders who never deposited, like the fee
+    // recipient, can start earning rewards by claiming
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + UserRewardCheckpoint::LEN,
+        seeds = [
+            b"reward_checkpoint".as_ref(),
+            vault.key().as_ref(),
+            user.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub user_reward_checkpoint: Box<Account<'info, UserRewardCheckpoint>>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+// remaining_accounts carries, for each reward the vault has collected, in index
+// order: the vault reward token account and the user's token account for that mint
+pub fn claim_rewards<'info>(
+    ctx: Context<'_, '_, '_, 'info, ClaimRewards<'info>>,
+) -> Result<()> {
+    ctx.accounts.reward_state.update(Clock::get()?.unix_timestamp)?;
+    let user_reward_checkpoint = &mut ctx.accounts.user_reward_checkpoint;
+    user_reward_checkpoint.vault = ctx.accounts.vault.key();
+    user_reward_checkpoint.user = ctx.accounts.user.key();
+    user_reward_checkpoint.settle(&ctx.accounts.reward_state, ctx.accounts.user_share.amount)?;
+    ctx.accounts.reward_state.record_shares(&mut ctx.accounts.user_reward_checkpoint, ctx.accounts.user_share.amount)?;
+    let user_reward_checkpoint = &mut ctx.accounts.user_reward_checkpoint;
+
+    let whirlpool_key = ctx.accounts.whirlpool.key();
+    let mut remaining_accounts = ctx.remaining_accounts.iter();
+    for reward_index in 0..NUM_REWARDS {
+        let reward = &ctx.accounts.reward_state.rewards[reward_index];
+        if reward.mint == Pubkey::default() {
+            continue;
+        }
+
+        let reward_token_account = remaining_accounts.next().ok_or(VaultError::InvalidRewardAccount)?;
+        let user_reward_account = remaining_accounts.next().ok_or(VaultError::InvalidRewardAccount)?;
+        require_keys_eq!(reward_token_account.key(), reward.token_account, VaultError::InvalidRewardAccount);
+        require_keys_eq!(token::accessor::mint(user_reward_account)?, reward.mint, VaultError::InvalidRewardAccount);
+
+        let amount = user_reward_checkpoint.owed[reward_index];
+        if amount == 0 {
+            continue;
+        }
+        user_reward_checkpoint.owed[reward_index] = 0;
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: reward_token_account.clone(),
+                    to: user_reward_account.clone(),
+                    authority: ctx.accounts.vault.to_account_info(),
+                },
+                &[&[b"vault".as_ref(), whirlpool_key.as_ref(), &[ctx.accounts.vault.bump]]],
+            ),
+            amount,
+        )?;
+    }
+
+    Ok(())
+}
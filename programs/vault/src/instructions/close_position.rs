@@ -6,8 +6,8 @@ use crate::state::Vault;
 
 #[derive(Accounts)]
 pub struct ClosePosition<'info> {
-  #[account(mut, constraint = admin.key() == vault.admin)]
-  pub admin: Signer<'info>,
+  #[account(mut, constraint = operator.key() == vault.operator)]
+  pub operator: Signer<'info>,
   
   pub whirlpool_program: Program<'info, WhirlpoolProgram>,
   
@@ -15,6 +15,7 @@ pub struct ClosePosition<'info> {
   pub whirlpool: Box<Account<'info, Whirlpool>>,
 
   #[account(
+    mut,
     seeds = [
         b"vault".as_ref(),
         whirlpool.key().as_ref(),
@@ -27,7 +28,7 @@ pub struct ClosePosition<'info> {
   #[account(mut)]
   pub receiver: UncheckedAccount<'info>,
 
-  #[account(mut, has_one = whirlpool)]
+  #[account(mut, has_one = whirlpool, address = vault.position)]
   pub position: Account<'info, Position>,
 
   #[account(mut, address = position.position_mint)]
@@ -56,11 +57,14 @@ pub fn close_position(
     token_program: ctx.accounts.token_program.to_account_info(),
   };
 
-  let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+  let whirlpool_key = ctx.accounts.whirlpool.key();
+  let signer_seeds: &[&[&[u8]]] = &[&[b"vault".as_ref(), whirlpool_key.as_ref(), &[ctx.accounts.vault.bump]]];
+  let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
 
   // execute CPI
   msg!("CPI: whirlpool close_position instruction");
   whirlpool_cpi::cpi::close_position(cpi_ctx)?;
 
+  ctx.accounts.vault.position = Pubkey::default();
   Ok(())
 }
\ No newline at end of file
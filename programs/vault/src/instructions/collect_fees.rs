@@ -1,19 +1,27 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Token, TokenAccount};
+use anchor_spl::token::{self, Mint, Token, TokenAccount};
 use whirlpool_cpi::{self, state::*, program::Whirlpool as WhirlpoolProgram};
 
-use crate::state::Vault;
+use crate::{
+  errors::VaultError,
+  state::{ObservationState, Vault},
+  util::{
+    accrue_fees, calculate_total_tokens, calculate_vault_value, check_price_deviation,
+    get_reference_price_x64, get_valuation_price, mint_shares, sqrt_price_to_price_x64, PriceSource,
+  },
+};
 
 #[derive(Accounts)]
 pub struct CollectFees<'info> {
-  #[account(mut, constraint = admin.key() == vault.admin)]
-  pub admin: Signer<'info>,
+  #[account(mut, constraint = operator.key() == vault.operator)]
+  pub operator: Signer<'info>,
 
   pub whirlpool_program: Program<'info, WhirlpoolProgram>,
 
   pub whirlpool: Box<Account<'info, Whirlpool>>,
 
   #[account(
+    mut,
     seeds = [
         b"vault".as_ref(),
         whirlpool.key().as_ref(),
@@ -30,16 +38,38 @@ pub struct CollectFees<'info> {
   )]
   pub position_token_account: Box<Account<'info, TokenAccount>>,
 
-  #[account(mut, constraint = token_vault_a.mint == whirlpool.token_mint_a)]
+  #[account(mut, address = vault.token_a_vault)]
   pub token_vault_a: Box<Account<'info, TokenAccount>>,
   #[account(mut, address = whirlpool.token_vault_a)]
   pub pool_token_vault_a: Box<Account<'info, TokenAccount>>,
 
-  #[account(mut, constraint = token_vault_b.mint == whirlpool.token_mint_b)]
+  #[account(mut, address = vault.token_b_vault)]
   pub token_vault_b: Box<Account<'info, TokenAccount>>,
   #[account(mut, address = whirlpool.token_vault_b)]
   pub pool_token_vault_b: Box<Account<'info, TokenAccount>>,
 
+  #[account(mut, address = vault.share_mint)]
+  pub share_mint: Box<Account<'info, Mint>>,
+  #[account(mut, address = vault.fee_recipient)]
+  pub fee_recipient: Option<Box<Account<'info, TokenAccount>>>,
+
+  /// CHECK: price feed recorded on the vault, only needed in oracle pricing modes
+  #[account(address = vault.token_a_price_feed)]
+  pub token_a_price_feed: Option<UncheckedAccount<'info>>,
+  /// CHECK: price feed recorded on the vault, only needed in oracle pricing modes
+  #[account(address = vault.token_b_price_feed)]
+  pub token_b_price_feed: Option<UncheckedAccount<'info>>,
+
+  #[account(
+    seeds = [
+        b"observation".as_ref(),
+        whirlpool.key().as_ref(),
+    ],
+    bump,
+    has_one = vault,
+  )]
+  pub observation_state: Option<Box<Account<'info, ObservationState>>>,
+
   #[account(address = token::ID)]
   pub token_program: Program<'info, Token>,
 }
@@ -47,6 +77,58 @@ pub struct CollectFees<'info> {
 pub fn collect_fees(
   ctx: Context<CollectFees>,
 ) -> Result<()> {
+  let vault = &ctx.accounts.vault;
+  let whirlpool = &ctx.accounts.whirlpool;
+  let (current_tick_index, sqrt_price) = get_valuation_price(
+    vault,
+    whirlpool,
+    ctx.accounts.observation_state.as_deref(),
+  )?;
+
+  let oracle_prices = PriceSource::new(
+    vault.pricing_mode,
+    ctx.accounts.token_a_price_feed.as_ref(),
+    ctx.accounts.token_b_price_feed.as_ref(),
+  )?
+  .token_prices(vault)?;
+
+  // Performance fees must not be taken on a manipulated pool price
+  check_price_deviation(
+    get_reference_price_x64(vault, sqrt_price, oracle_prices)?,
+    sqrt_price_to_price_x64(whirlpool.sqrt_price)?,
+    vault.max_price_deviation_bps,
+  )?;
+
+  let (total_token_a_amount, total_token_b_amount) = calculate_total_tokens(
+    ctx.accounts.token_vault_a.amount,
+    ctx.accounts.token_vault_b.amount,
+    ctx.accounts.position.liquidity,
+    current_tick_index,
+    sqrt_price,
+    vault.lower_tick,
+    vault.upper_tick,
+  )?;
+
+  // Accrue management and performance fees before harvesting
+  let total_value = calculate_vault_value(total_token_a_amount, total_token_b_amount, oracle_prices, sqrt_price)?;
+  let fee_shares = accrue_fees(
+    &mut ctx.accounts.vault,
+    total_value,
+    ctx.accounts.share_mint.supply,
+    Clock::get()?.unix_timestamp,
+  )?;
+  if fee_shares > 0 {
+    let fee_recipient = ctx.accounts.fee_recipient.as_ref().ok_or(VaultError::MissingFeeRecipient)?;
+    mint_shares(
+      ctx.accounts.token_program.to_account_info(),
+      ctx.accounts.share_mint.to_account_info(),
+      fee_recipient.to_account_info(),
+      ctx.accounts.vault.to_account_info(),
+      &[&[b"vault".as_ref(), ctx.accounts.whirlpool.key().as_ref(), &[ctx.accounts.vault.bump]]],
+      fee_shares,
+    )?;
+  }
+
   let cpi_program = ctx.accounts.whirlpool_program.to_account_info();
 
   let cpi_accounts = whirlpool_cpi::cpi::accounts::CollectFees {
@@ -61,7 +143,11 @@ pub fn collect_fees(
     token_program: ctx.accounts.token_program.to_account_info(),
   };
 
-  let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+  let whirlpool_key = ctx.accounts.whirlpool.key();
+  let signer_seeds: &[&[&[u8]]] = &[&[b"vault".as_ref(), whirlpool_key.as_ref(), &[ctx.accounts.vault.bump]]];
+
+  // The vault owns the position token, so it signs as the position authority
+  let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
 
   // execute CPI
   msg!("CPI: whirlpool collect_fees instruction");
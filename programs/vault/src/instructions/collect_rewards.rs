@@ -0,0 +1,270 @@
+use anchor_lang::prelude::*;
+use anchor_lang::system_program::{self, Allocate, Assign, CreateAccount, Transfer};
+use anchor_spl::token::{self, InitializeAccount3, Token, TokenAccount};
+use whirlpool_cpi::{self, state::*, program::Whirlpool as WhirlpoolProgram};
+
+use crate::{
+  errors::VaultError,
+  state::{RewardLedger, RewardState, Vault, NUM_REWARDS},
+};
+
+#[derive(Accounts)]
+pub struct CollectRewards<'info> {
+  #[account(mut, constraint = fee_manager.key() == vault.fee_manager)]
+  pub fee_manager: Signer<'info>,
+
+  pub whirlpool_program: Program<'info, WhirlpoolProgram>,
+
+  #[account(mut)]
+  pub whirlpool: Box<Account<'info, Whirlpool>>,
+
+  #[account(
+    seeds = [
+        b"vault".as_ref(),
+        whirlpool.key().as_ref(),
+    ],
+    bump,
+  )]
+  pub vault: Box<Account<'info, Vault>>,
+
+  #[account(
+    init_if_needed,
+    payer = fee_manager,
+    space = 8 + RewardLedger::LEN,
+    seeds = [
+        b"reward_ledger".as_ref(),
+        whirlpool.key().as_ref(),
+    ],
+    bump,
+  )]
+  pub reward_ledger: Box<Account<'info, RewardLedger>>,
+
+  #[account(
+    mut,
+    seeds = [
+        b"reward_state".as_ref(),
+        whirlpool.key().as_ref(),
+    ],
+    bump,
+    has_one = vault,
+  )]
+  pub reward_state: Box<Account<'info, RewardState>>,
+
+  #[account(mut, has_one = whirlpool)]
+  pub position: Box<Account<'info, Position>>,
+  #[account(
+      constraint = position_token_account.mint == position.position_mint,
+      constraint = position_token_account.amount == 1
+  )]
+  pub position_token_account: Box<Account<'info, TokenAccount>>,
+
+  #[account(mut, has_one = whirlpool)]
+  pub tick_array_lower: AccountLoader<'info, TickArray>,
+  #[account(mut, has_one = whirlpool)]
+  pub tick_array_upper: AccountLoader<'info, TickArray>,
+
+  #[account(address = token::ID)]
+  pub token_program: Program<'info, Token>,
+  pub system_program: Program<'info, System>,
+  pub rent: Sysvar<'info, Rent>,
+}
+
+// remaining_accounts carries, for each initialised reward index in order:
+// the reward mint, the vault reward token account (PDA of ["reward_vault", vault, mint],
+// created on first use) and the whirlpool's reward vault
+pub fn collect_rewards<'info>(
+  ctx: Context<'_, '_, '_, 'info, CollectRewards<'info>>,
+) -> Result<()> {
+  // Bring the position's owed rewards up to date before collecting them
+  msg!("CPI: whirlpool update_fees_and_rewards instruction");
+  whirlpool_cpi::cpi::update_fees_and_rewards(CpiContext::new(
+    ctx.accounts.whirlpool_program.to_account_info(),
+    whirlpool_cpi::cpi::accounts::UpdateFeesAndRewards {
+      whirlpool: ctx.accounts.whirlpool.to_account_info(),
+      position: ctx.accounts.position.to_account_info(),
+      tick_array_lower: ctx.accounts.tick_array_lower.to_account_info(),
+      tick_array_upper: ctx.accounts.tick_array_upper.to_account_info(),
+    },
+  ))?;
+
+  PositionRewards {
+    program_id: ctx.program_id,
+    payer: ctx.accounts.fee_manager.to_account_info(),
+    whirlpool_program: ctx.accounts.whirlpool_program.to_account_info(),
+    whirlpool: &ctx.accounts.whirlpool,
+    vault: &ctx.accounts.vault,
+    position: ctx.accounts.position.to_account_info(),
+    position_token_account: ctx.accounts.position_token_account.to_account_info(),
+    token_program: ctx.accounts.token_program.to_account_info(),
+    system_program: ctx.accounts.system_program.to_account_info(),
+    rent: &ctx.accounts.rent,
+  }
+  .collect(ctx.remaining_accounts, &mut ctx.accounts.reward_ledger, &mut ctx.accounts.reward_state)
+}
+
+// Accounts needed to collect the vault position's rewards, shared with rebalance,
+// which has to collect them before it can close the position
+pub struct PositionRewards<'a, 'info> {
+  pub program_id: &'a Pubkey,
+  // Pays for reward token accounts created on first use
+  pub payer: AccountInfo<'info>,
+  pub whirlpool_program: AccountInfo<'info>,
+  pub whirlpool: &'a Account<'info, Whirlpool>,
+  pub vault: &'a Account<'info, Vault>,
+  pub position: AccountInfo<'info>,
+  pub position_token_account: AccountInfo<'info>,
+  pub token_program: AccountInfo<'info>,
+  pub system_program: AccountInfo<'info>,
+  pub rent: &'a Rent,
+}
+
+impl<'a, 'info> PositionRewards<'a, 'info> {
+  // Collects every initialised reward into its vault reward token account, laid out in
+  // remaining_accounts as collect_rewards describes, and hands it to the share holders
+  pub fn collect(
+    &self,
+    remaining_accounts: &[AccountInfo<'info>],
+    reward_ledger: &mut RewardLedger,
+    reward_state: &mut RewardState,
+  ) -> Result<()> {
+    let whirlpool_key = self.whirlpool.key();
+    let vault_key = self.vault.key();
+    let vault_seeds: &[&[u8]] = &[b"vault".as_ref(), whirlpool_key.as_ref(), &[self.vault.bump]];
+
+    // Stream what was already handed over before adding the new rewards
+    reward_state.update(Clock::get()?.unix_timestamp)?;
+    reward_ledger.vault = vault_key;
+
+    let mut remaining_accounts = remaining_accounts.iter();
+    for reward_index in 0..NUM_REWARDS {
+      let reward_info = &self.whirlpool.reward_infos[reward_index];
+      if reward_info.mint == Pubkey::default() {
+        continue;
+      }
+
+      let reward_mint = remaining_accounts.next().ok_or(VaultError::InvalidRewardAccount)?;
+      let reward_token_account = remaining_accounts.next().ok_or(VaultError::InvalidRewardAccount)?;
+      let pool_reward_vault = remaining_accounts.next().ok_or(VaultError::InvalidRewardAccount)?;
+      require_keys_eq!(reward_mint.key(), reward_info.mint, VaultError::InvalidRewardAccount);
+      require_keys_eq!(pool_reward_vault.key(), reward_info.vault, VaultError::InvalidRewardAccount);
+
+      let (expected_token_account, token_account_bump) = Pubkey::find_program_address(
+        &[b"reward_vault".as_ref(), vault_key.as_ref(), reward_mint.key.as_ref()],
+        self.program_id,
+      );
+      require_keys_eq!(reward_token_account.key(), expected_token_account, VaultError::InvalidRewardAccount);
+
+      if reward_token_account.data_is_empty() {
+        msg!("Creating vault reward token account for reward {}", reward_index);
+        let token_account_seeds: &[&[u8]] =
+          &[b"reward_vault".as_ref(), vault_key.as_ref(), reward_mint.key.as_ref(), &[token_account_bump]];
+        let rent_lamports = self.rent.minimum_balance(TokenAccount::LEN);
+        let current_lamports = reward_token_account.lamports();
+        if current_lamports == 0 {
+          system_program::create_account(
+            CpiContext::new_with_signer(
+              self.system_program.clone(),
+              CreateAccount {
+                from: self.payer.clone(),
+                to: reward_token_account.clone(),
+              },
+              &[token_account_seeds],
+            ),
+            rent_lamports,
+            TokenAccount::LEN as u64,
+            &token::ID,
+          )?;
+        } else {
+          // Someone already sent lamports to the address, which makes create_account fail,
+          // so top it up to rent exemption and allocate and assign it instead
+          let top_up = rent_lamports.saturating_sub(current_lamports);
+          if top_up > 0 {
+            system_program::transfer(
+              CpiContext::new(
+                self.system_program.clone(),
+                Transfer {
+                  from: self.payer.clone(),
+                  to: reward_token_account.clone(),
+                },
+              ),
+              top_up,
+            )?;
+          }
+          system_program::allocate(
+            CpiContext::new_with_signer(
+              self.system_program.clone(),
+              Allocate { account_to_allocate: reward_token_account.clone() },
+              &[token_account_seeds],
+            ),
+            TokenAccount::LEN as u64,
+          )?;
+          system_program::assign(
+            CpiContext::new_with_signer(
+              self.system_program.clone(),
+              Assign { account_to_assign: reward_token_account.clone() },
+              &[token_account_seeds],
+            ),
+            &token::ID,
+          )?;
+        }
+        token::initialize_account3(CpiContext::new(
+          self.token_program.clone(),
+          InitializeAccount3 {
+            account: reward_token_account.clone(),
+            mint: reward_mint.clone(),
+            authority: self.vault.to_account_info(),
+          },
+        ))?;
+      } else {
+        require!(
+          *reward_token_account.owner == token::ID
+            && token::accessor::mint(reward_token_account)? == reward_info.mint
+            && token::accessor::authority(reward_token_account)? == vault_key,
+          VaultError::InvalidRewardAccount
+        );
+      }
+
+      let amount_before = token::accessor::amount(reward_token_account)?;
+
+      msg!("CPI: whirlpool collect_reward instruction");
+      whirlpool_cpi::cpi::collect_reward(
+        CpiContext::new_with_signer(
+          self.whirlpool_program.clone(),
+          whirlpool_cpi::cpi::accounts::CollectReward {
+            whirlpool: self.whirlpool.to_account_info(),
+            position_authority: self.vault.to_account_info(),
+            position: self.position.clone(),
+            position_token_account: self.position_token_account.clone(),
+            reward_owner_account: reward_token_account.clone(),
+            reward_vault: pool_reward_vault.clone(),
+            token_program: self.token_program.clone(),
+          },
+          &[vault_seeds],
+        ),
+        reward_index as u8,
+      )?;
+
+      let collected = token::accessor::amount(reward_token_account)?.saturating_sub(amount_before);
+      let entry = &mut reward_ledger.rewards[reward_index];
+      entry.mint = reward_info.mint;
+      entry.token_account = reward_token_account.key();
+      entry.total_collected = entry.total_collected
+        .checked_add(collected)
+        .ok_or(VaultError::ArithmeticOverflow)?;
+      entry.pending = entry.pending
+        .checked_add(collected)
+        .ok_or(VaultError::ArithmeticOverflow)?;
+
+      // Hand the pending rewards over to the share holder accumulator
+      let accumulator = &mut reward_state.rewards[reward_index];
+      accumulator.mint = entry.mint;
+      accumulator.token_account = entry.token_account;
+      accumulator.undistributed = accumulator.undistributed
+        .checked_add(entry.pending)
+        .ok_or(VaultError::ArithmeticOverflow)?;
+      entry.pending = 0;
+    }
+
+    Ok(())
+  }
+}
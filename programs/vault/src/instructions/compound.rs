@@ -0,0 +1,233 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Token, TokenAccount};
+use whirlpool_cpi::{self, state::*, program::Whirlpool as WhirlpoolProgram};
+
+use crate::{
+  errors::VaultError,
+  state::{ObservationState, Vault},
+  util::{
+    calculate_fee_amount, calculate_max_liquidity, check_price_deviation, get_reference_price_x64,
+    get_valuation_price, sqrt_price_to_price_x64, PriceSource, Rounding,
+  },
+};
+
+// Permissionless, so keepers can run it without the admin key
+#[derive(Accounts)]
+pub struct Compound<'info> {
+  pub caller: Signer<'info>,
+
+  pub whirlpool_program: Program<'info, WhirlpoolProgram>,
+
+  #[account(mut)]
+  pub whirlpool: Box<Account<'info, Whirlpool>>,
+
+  #[account(
+    mut,
+    seeds = [
+        b"vault".as_ref(),
+        whirlpool.key().as_ref(),
+    ],
+    bump,
+  )]
+  pub vault: Box<Account<'info, Vault>>,
+
+  #[account(mut, has_one = whirlpool)]
+  pub position: Box<Account<'info, Position>>,
+  #[account(
+      constraint = position_token_account.mint == position.position_mint,
+      constraint = position_token_account.amount == 1
+  )]
+  pub position_token_account: Box<Account<'info, TokenAccount>>,
+
+  #[account(mut, address = vault.token_a_vault)]
+  pub token_vault_a: Box<Account<'info, TokenAccount>>,
+  #[account(mut, address = whirlpool.token_vault_a)]
+  pub pool_token_vault_a: Box<Account<'info, TokenAccount>>,
+
+  #[account(mut, address = vault.token_b_vault)]
+  pub token_vault_b: Box<Account<'info, TokenAccount>>,
+  #[account(mut, address = whirlpool.token_vault_b)]
+  pub pool_token_vault_b: Box<Account<'info, TokenAccount>>,
+
+  #[account(mut, token::mint = whirlpool.token_mint_a)]
+  pub caller_token_a: Box<Account<'info, TokenAccount>>,
+  #[account(mut, token::mint = whirlpool.token_mint_b)]
+  pub caller_token_b: Box<Account<'info, TokenAccount>>,
+
+  #[account(mut, has_one = whirlpool)]
+  pub tick_array_lower: AccountLoader<'info, TickArray>,
+  #[account(mut, has_one = whirlpool)]
+  pub tick_array_upper: AccountLoader<'info, TickArray>,
+
+  /// CHECK: price feed recorded on the vault, only needed in oracle pricing modes
+  #[account(address = vault.token_a_price_feed)]
+  pub token_a_price_feed: Option<UncheckedAccount<'info>>,
+  /// CHECK: price feed recorded on the vault, only needed in oracle pricing modes
+  #[account(address = vault.token_b_price_feed)]
+  pub token_b_price_feed: Option<UncheckedAccount<'info>>,
+
+  #[account(
+    seeds = [
+        b"observation".as_ref(),
+        whirlpool.key().as_ref(),
+    ],
+    bump,
+    has_one = vault,
+  )]
+  pub observation_state: Option<Box<Account<'info, ObservationState>>>,
+
+  #[account(address = token::ID)]
+  pub token_program: Program<'info, Token>,
+}
+
+pub fn compound(
+  ctx: Context<Compound>,
+) -> Result<()> {
+  let current_slot = Clock::get()?.slot;
+  let vault = &ctx.accounts.vault;
+  let whirlpool = &ctx.accounts.whirlpool;
+  require!(!vault.paused, VaultError::VaultPaused);
+  require!(
+    current_slot >= vault.last_compound_slot.saturating_add(vault.min_compound_interval),
+    VaultError::CompoundTooSoon
+  );
+
+  // Fees are added at the pool's spot price, a caller must not be able to push
+  // the pool before cranking for the bounty
+  let (_, valuation_sqrt_price) = get_valuation_price(
+    vault,
+    whirlpool,
+    ctx.accounts.observation_state.as_deref(),
+  )?;
+  let oracle_prices = PriceSource::new(
+    vault.pricing_mode,
+    ctx.accounts.token_a_price_feed.as_ref(),
+    ctx.accounts.token_b_price_feed.as_ref(),
+  )?
+  .token_prices(vault)?;
+  check_price_deviation(
+    get_reference_price_x64(vault, valuation_sqrt_price, oracle_prices)?,
+    sqrt_price_to_price_x64(whirlpool.sqrt_price)?,
+    vault.max_price_deviation_bps,
+  )?;
+
+  ctx.accounts.vault.last_compound_slot = current_slot;
+
+  let whirlpool_key = ctx.accounts.whirlpool.key();
+  let signer_seeds: &[&[&[u8]]] = &[&[b"vault".as_ref(), whirlpool_key.as_ref(), &[ctx.accounts.vault.bump]]];
+
+  // Bring the position's owed fees up to date before collecting them
+  msg!("CPI: whirlpool update_fees_and_rewards instruction");
+  whirlpool_cpi::cpi::update_fees_and_rewards(CpiContext::new(
+    ctx.accounts.whirlpool_program.to_account_info(),
+    whirlpool_cpi::cpi::accounts::UpdateFeesAndRewards {
+      whirlpool: ctx.accounts.whirlpool.to_account_info(),
+      position: ctx.accounts.position.to_account_info(),
+      tick_array_lower: ctx.accounts.tick_array_lower.to_account_info(),
+      tick_array_upper: ctx.accounts.tick_array_upper.to_account_info(),
+    },
+  ))?;
+
+  let token_a_before = ctx.accounts.token_vault_a.amount;
+  let token_b_before = ctx.accounts.token_vault_b.amount;
+
+  msg!("CPI: whirlpool collect_fees instruction");
+  whirlpool_cpi::cpi::collect_fees(CpiContext::new_with_signer(
+    ctx.accounts.whirlpool_program.to_account_info(),
+    whirlpool_cpi::cpi::accounts::CollectFees {
+      whirlpool: ctx.accounts.whirlpool.to_account_info(),
+      position_authority: ctx.accounts.vault.to_account_info(),
+      position: ctx.accounts.position.to_account_info(),
+      position_token_account: ctx.accounts.position_token_account.to_account_info(),
+      token_owner_account_a: ctx.accounts.token_vault_a.to_account_info(),
+      token_vault_a: ctx.accounts.pool_token_vault_a.to_account_info(),
+      token_owner_account_b: ctx.accounts.token_vault_b.to_account_info(),
+      token_vault_b: ctx.accounts.pool_token_vault_b.to_account_info(),
+      token_program: ctx.accounts.token_program.to_account_info(),
+    },
+    signer_seeds,
+  ))?;
+
+  ctx.accounts.token_vault_a.reload()?;
+  ctx.accounts.token_vault_b.reload()?;
+
+  // Pay the caller its bounty out of the harvested fees
+  let bounty_a = calculate_fee_amount(
+    ctx.accounts.token_vault_a.amount.saturating_sub(token_a_before),
+    ctx.accounts.vault.compound_bounty_bps,
+    Rounding::Down,
+  )?;
+  let bounty_b = calculate_fee_amount(
+    ctx.accounts.token_vault_b.amount.saturating_sub(token_b_before),
+    ctx.accounts.vault.compound_bounty_bps,
+    Rounding::Down,
+  )?;
+  if bounty_a > 0 {
+    token::transfer(
+      CpiContext::new_with_signer(
+        ctx.accounts.token_program.to_account_info(),
+        token::Transfer {
+          from: ctx.accounts.token_vault_a.to_account_info(),
+          to: ctx.accounts.caller_token_a.to_account_info(),
+          authority: ctx.accounts.vault.to_account_info(),
+        },
+        signer_seeds,
+      ),
+      bounty_a,
+    )?;
+  }
+  if bounty_b > 0 {
+    token::transfer(
+      CpiContext::new_with_signer(
+        ctx.accounts.token_program.to_account_info(),
+        token::Transfer {
+          from: ctx.accounts.token_vault_b.to_account_info(),
+          to: ctx.accounts.caller_token_b.to_account_info(),
+          authority: ctx.accounts.vault.to_account_info(),
+        },
+        signer_seeds,
+      ),
+      bounty_b,
+    )?;
+  }
+
+  // Size the liquidity from the idle balances, collected fees included
+  let quote = calculate_max_liquidity(
+    ctx.accounts.token_vault_a.amount - bounty_a,
+    ctx.accounts.token_vault_b.amount - bounty_b,
+    ctx.accounts.whirlpool.tick_current_index,
+    ctx.accounts.whirlpool.sqrt_price,
+    ctx.accounts.position.tick_lower_index,
+    ctx.accounts.position.tick_upper_index,
+  )?;
+  if quote.liquidity_delta == 0 {
+    msg!("Nothing to compound");
+    return Ok(());
+  }
+
+  msg!("CPI: whirlpool increase_liquidity instruction");
+  whirlpool_cpi::cpi::increase_liquidity(
+    CpiContext::new_with_signer(
+      ctx.accounts.whirlpool_program.to_account_info(),
+      whirlpool_cpi::cpi::accounts::ModifyLiquidity {
+        whirlpool: ctx.accounts.whirlpool.to_account_info(),
+        token_program: ctx.accounts.token_program.to_account_info(),
+        position_authority: ctx.accounts.vault.to_account_info(),
+        position: ctx.accounts.position.to_account_info(),
+        position_token_account: ctx.accounts.position_token_account.to_account_info(),
+        token_owner_account_a: ctx.accounts.token_vault_a.to_account_info(),
+        token_owner_account_b: ctx.accounts.token_vault_b.to_account_info(),
+        token_vault_a: ctx.accounts.pool_token_vault_a.to_account_info(),
+        token_vault_b: ctx.accounts.pool_token_vault_b.to_account_info(),
+        tick_array_lower: ctx.accounts.tick_array_lower.to_account_info(),
+        tick_array_upper: ctx.accounts.tick_array_upper.to_account_info(),
+      },
+      signer_seeds,
+    ),
+    quote.liquidity_delta,
+    quote.token_max_a,
+    quote.token_max_b,
+  )?;
+
+  Ok(())
+}
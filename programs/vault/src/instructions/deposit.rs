@@ -1,9 +1,13 @@
 use anchor_lang::prelude::*;
 use anchor_spl::token::{self, Token, TokenAccount, Mint, Transfer};
 use whirlpool_cpi::state::{Position, Whirlpool};
-use crate::util::calculate_total_tokens;
-use crate::utils::calculate_shares;
-use crate::state::Vault;
+use crate::util::{
+    accrue_fees, calculate_fee_amount, calculate_total_tokens, calculate_vault_value, check_price_deviation,
+    get_reference_price_x64, get_valuation_price, mint_shares, sqrt_price_to_price_x64, PriceSource, Rounding,
+};
+use crate::utils::{calculate_proportional_deposit, calculate_shares, split_dead_shares};
+use crate::errors::VaultError;
+use crate::state::{DepositMode, ObservationState, PricingMode, RewardState, UserRewardCheckpoint, Vault};
 
 #[derive(Accounts)]
 pub struct Deposit<'info> {
@@ -14,6 +18,7 @@ pub struct Deposit<'info> {
     pub whirlpool: Box<Account<'info, Whirlpool>>,
 
     #[account(
+        mut,
         seeds = [
             b"vault".as_ref(),
             whirlpool.key().as_ref(),
@@ -22,7 +27,8 @@ pub struct Deposit<'info> {
     )]
     pub vault: Box<Account<'info, Vault>>,
 
-    #[account(mut, has_one = whirlpool)]
+    // Required whenever the vault has a position open
+    #[account(mut, has_one = whirlpool, address = vault.position)]
     pub position: Option<Account<'info, Position>>,
     
     #[account(mut, constraint = user_token_a.mint == vault.token_a_mint)]
@@ -36,24 +42,197 @@ pub struct Deposit<'info> {
     
     #[account(mut, constraint = share_mint.key() == vault.share_mint)]
     pub share_mint: Account<'info, Mint>,
-    #[account(mut, constraint = user_share.mint == vault.share_mint)]
+    #[account(
+        mut,
+        constraint = user_share.mint == vault.share_mint,
+        constraint = user_share.owner == user.key(),
+    )]
     pub user_share: Account<'info, TokenAccount>,
+    // Holds the dead shares minted by the first deposit, nothing can move them out
+    #[account(
+        mut,
+        seeds = [
+            b"dead_shares".as_ref(),
+            vault.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub dead_shares: Box<Account<'info, TokenAccount>>,
+    #[account(mut, address = vault.fee_recipient)]
+    pub fee_recipient: Option<Box<Account<'info, TokenAccount>>>,
+
+    /// CHECK: price feed recorded on the vault, only needed in oracle pricing modes
+    #[account(address = vault.token_a_price_feed)]
+    pub token_a_price_feed: Option<UncheckedAccount<'info>>,
+    /// CHECK: price feed recorded on the vault, only needed in oracle pricing modes
+    #[account(address = vault.token_b_price_feed)]
+    pub token_b_price_feed: Option<UncheckedAccount<'info>>,
+
+    #[account(
+        seeds = [
+            b"observation".as_ref(),
+            whirlpool.key().as_ref(),
+        ],
+        bump,
+        has_one = vault,
+    )]
+    pub observation_state: Option<Box<Account<'info, ObservationState>>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"reward_state".as_ref(),
+            whirlpool.key().as_ref(),
+        ],
+        bump,
+        has_one = vault,
+    )]
+    pub reward_state: Box<Account<'info, RewardState>>,
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + UserRewardCheckpoint::LEN,
+        seeds = [
+            b"reward_checkpoint".as_ref(),
+            vault.key().as_ref(),
+            user.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub user_reward_checkpoint: Box<Account<'info, UserRewardCheckpoint>>,
     
     pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
 }
 
 pub fn deposit(
     ctx: Context<Deposit>,
     amount_a: u64,
     amount_b: u64,
+    min_shares_out: u64,
+    deadline: Option<i64>,
 ) -> Result<()> {
+    require!(!ctx.accounts.vault.paused, VaultError::VaultPaused);
+
+    // Refuse to execute a deposit that sat in the mempool past the user's deadline
+    if let Some(deadline) = deadline {
+        require!(Clock::get()?.unix_timestamp <= deadline, VaultError::DeadlineExceeded);
+    }
+
+    // Stream rewards to the current holders before the share supply changes
+    ctx.accounts.reward_state.update(Clock::get()?.unix_timestamp)?;
+    let user_reward_checkpoint = &mut ctx.accounts.user_reward_checkpoint;
+    user_reward_checkpoint.vault = ctx.accounts.vault.key();
+    user_reward_checkpoint.user = ctx.accounts.user.key();
+    user_reward_checkpoint.settle(&ctx.accounts.reward_state, ctx.accounts.user_share.amount)?;
+
+    // Leaving the position out would undervalue the vault and over-mint shares
+    require!(
+        ctx.accounts.position.is_some() || ctx.accounts.vault.position == Pubkey::default(),
+        VaultError::MissingPosition
+    );
+
     let vault = &ctx.accounts.vault;
     let total_shares = ctx.accounts.share_mint.supply;
     let whirlpool = &ctx.accounts.whirlpool;
     let position = &ctx.accounts.position;
-    // Get current tick index and sqrt_price from whirlpool
-    let current_tick_index = whirlpool.tick_current_index;
-    let sqrt_price = whirlpool.sqrt_price;
+    // Get the tick index and sqrt_price the vault is valued at
+    let (current_tick_index, sqrt_price) = get_valuation_price(
+        vault,
+        whirlpool,
+        ctx.accounts.observation_state.as_deref(),
+    )?;
+
+    // Value the vault before the deposit lands, including tokens in the Whirlpool position
+    let (total_token_a_amount, total_token_b_amount) = if let Some(position) = position {
+        calculate_total_tokens(
+            ctx.accounts.token_a_vault.amount,
+            ctx.accounts.token_b_vault.amount,
+            position.liquidity,
+            current_tick_index,
+            sqrt_price,
+            vault.lower_tick,
+            vault.upper_tick,
+        )?
+    } else {
+        (ctx.accounts.token_a_vault.amount, ctx.accounts.token_b_vault.amount)
+    };
+
+    let oracle_prices = PriceSource::new(
+        vault.pricing_mode,
+        ctx.accounts.token_a_price_feed.as_ref(),
+        ctx.accounts.token_b_price_feed.as_ref(),
+    )?
+    .token_prices(vault)?;
+
+    // Refuse to mint against a pool pushed away from the reference price
+    check_price_deviation(
+        get_reference_price_x64(vault, sqrt_price, oracle_prices)?,
+        sqrt_price_to_price_x64(whirlpool.sqrt_price)?,
+        vault.max_price_deviation_bps,
+    )?;
+
+    if vault.pricing_mode == PricingMode::Whirlpool {
+        // The spot price can be pushed within a single transaction, so it must
+        // stay close to the reference tick snapshotted by the admin
+        let tick_deviation = current_tick_index.abs_diff(vault.reference_tick);
+        require!(tick_deviation <= vault.max_tick_deviation, VaultError::TickDeviationTooHigh);
+    }
+
+    let deposit_value = calculate_vault_value(amount_a, amount_b, oracle_prices, sqrt_price)?;
+    let total_value = calculate_vault_value(total_token_a_amount, total_token_b_amount, oracle_prices, sqrt_price)?;
+    let decimal = if oracle_prices.is_some() {
+        vault.token_a_decimal.max(vault.token_b_decimal)
+    } else {
+        vault.token_b_decimal
+    };
+
+    // Accrue management and performance fees on the vault as it stood before this deposit
+    let fee_shares = accrue_fees(
+        &mut ctx.accounts.vault,
+        total_value,
+        total_shares,
+        Clock::get()?.unix_timestamp,
+    )?;
+    if fee_shares > 0 {
+        let fee_recipient = ctx.accounts.fee_recipient.as_ref().ok_or(VaultError::MissingFeeRecipient)?;
+        mint_shares(
+            ctx.accounts.token_program.to_account_info(),
+            ctx.accounts.share_mint.to_account_info(),
+            fee_recipient.to_account_info(),
+            ctx.accounts.vault.to_account_info(),
+            &[&[b"vault", ctx.accounts.whirlpool.key().as_ref(), &[ctx.accounts.vault.bump]]],
+            fee_shares,
+        )?;
+    }
+    let total_shares = total_shares
+        .checked_add(fee_shares)
+        .ok_or(VaultError::ArithmeticOverflow)?;
+
+    // A proportional vault only takes the ratio it already holds and leaves the
+    // excess with the user, the first deposit still sets the price from its value
+    let (share_amount, amount_a, amount_b) =
+        if ctx.accounts.vault.deposit_mode == DepositMode::Proportional && total_shares > 0 {
+            calculate_proportional_deposit(
+                amount_a,
+                amount_b,
+                total_token_a_amount,
+                total_token_b_amount,
+                total_shares,
+            )?
+        } else {
+            let share_amount = calculate_shares(
+                deposit_value,
+                total_value,
+                total_shares,
+                decimal,
+            )?;
+            (share_amount, amount_a, amount_b)
+        };
+
+    // The first deposit locks dead shares in the vault, so donating to an empty
+    // vault cannot inflate the share price against the next depositor
+    let (dead_share_amount, share_amount) = split_dead_shares(share_amount, total_shares)?;
 
     if amount_a > 0 {
         token::transfer(
@@ -83,49 +262,52 @@ pub fn deposit(
         )?;
     }
 
-    // Calculate total value including tokens in Whirlpool position
-    let (total_token_a_amount, total_token_b_amount) = if let Some(position) = position {
-        calculate_total_tokens(
-            ctx.accounts.token_a_vault.amount,
-            ctx.accounts.token_b_vault.amount,
-            position.liquidity,
-            current_tick_index,
-            sqrt_price,
-            vault.lower_tick,
-            vault.upper_tick,
-        )?
-    } else {
-        (ctx.accounts.token_a_vault.amount, ctx.accounts.token_b_vault.amount)
-    };
-
-    let max_decimal = ctx.accounts.vault.token_a_decimal.max(ctx.accounts.vault.token_b_decimal);
-    let share_amount = calculate_shares(
-        amount_a,
-        amount_b,
-        total_token_a_amount,
-        total_token_b_amount,
-        ctx.accounts.vault.token_a_price,
-        ctx.accounts.vault.token_b_price,
-        total_shares,
-        max_decimal
-    );
+    if dead_share_amount > 0 {
+        token::mint_to(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::MintTo {
+                    mint: ctx.accounts.share_mint.to_account_info(),
+                    to: ctx.accounts.dead_shares.to_account_info(),
+                    authority: ctx.accounts.vault.to_account_info(),
+                },
+                &[&[
+                    b"vault",
+                    ctx.accounts.whirlpool.key().as_ref(),
+                    &[ctx.accounts.vault.bump]
+                ]],
+            ),
+            dead_share_amount,
+        )?;
+    }
 
-    token::mint_to(
-        CpiContext::new_with_signer(
+    // The entry fee is taken out of the minted shares
+    let deposit_fee_shares = calculate_fee_amount(share_amount, ctx.accounts.vault.deposit_fee_bps, Rounding::Up)?;
+    let user_shares = share_amount - deposit_fee_shares;
+    require!(user_shares >= min_shares_out, VaultError::SlippageExceeded);
+    if deposit_fee_shares > 0 {
+        let fee_recipient = ctx.accounts.fee_recipient.as_ref().ok_or(VaultError::MissingFeeRecipient)?;
+        mint_shares(
             ctx.accounts.token_program.to_account_info(),
-            token::MintTo {
-                mint: ctx.accounts.share_mint.to_account_info(),
-                to: ctx.accounts.user_share.to_account_info(),
-                authority: ctx.accounts.vault.to_account_info(),
-            },
-            &[&[
-                b"vault",
-                ctx.accounts.whirlpool.key().as_ref(),
-                &[ctx.accounts.vault.bump]
-            ]],
-        ),
-        share_amount,
+            ctx.accounts.share_mint.to_account_info(),
+            fee_recipient.to_account_info(),
+            ctx.accounts.vault.to_account_info(),
+            &[&[b"vault", ctx.accounts.whirlpool.key().as_ref(), &[ctx.accounts.vault.bump]]],
+            deposit_fee_shares,
+        )?;
+    }
+
+    mint_shares(
+        ctx.accounts.token_program.to_account_info(),
+        ctx.accounts.share_mint.to_account_info(),
+        ctx.accounts.user_share.to_account_info(),
+        ctx.accounts.vault.to_account_info(),
+        &[&[b"vault", ctx.accounts.whirlpool.key().as_ref(), &[ctx.accounts.vault.bump]]],
+        user_shares,
     )?;
 
+    ctx.accounts.user_share.reload()?;
+    ctx.accounts.reward_state.record_shares(&mut ctx.accounts.user_reward_checkpoint, ctx.accounts.user_share.amount)?;
+
     Ok(())
 }
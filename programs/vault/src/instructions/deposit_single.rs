@@ -0,0 +1,372 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Token, TokenAccount, Mint, Transfer};
+use whirlpool_cpi::{self, program::Whirlpool as WhirlpoolProgram, state::{Position, TickArray, Whirlpool}};
+use crate::util::{
+    accrue_fees, calculate_fee_amount, calculate_min_swap_output, calculate_swap_to_ratio, calculate_total_tokens,
+    calculate_vault_value, check_price_deviation, get_reference_price_x64, get_valuation_price, mint_shares,
+    sqrt_price_to_price_x64, PriceSource, Rounding, MAX_SQRT_PRICE, MIN_SQRT_PRICE,
+};
+use crate::utils::{calculate_shares, split_dead_shares};
+use crate::errors::VaultError;
+use crate::state::{DepositMode, ObservationState, PricingMode, RewardState, UserRewardCheckpoint, Vault};
+
+#[derive(Accounts)]
+pub struct DepositSingle<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    pub whirlpool_program: Program<'info, WhirlpoolProgram>,
+
+    #[account(mut, constraint = whirlpool.key() == vault.whirlpool)]
+    pub whirlpool: Box<Account<'info, Whirlpool>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"vault".as_ref(),
+            whirlpool.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub vault: Box<Account<'info, Vault>>,
+
+    // Required whenever the vault has a position open
+    #[account(has_one = whirlpool, address = vault.position)]
+    pub position: Option<Box<Account<'info, Position>>>,
+
+    // Holds the one token the user deposits, either token A or token B
+    #[account(
+        mut,
+        constraint = user_token.mint == vault.token_a_mint || user_token.mint == vault.token_b_mint,
+    )]
+    pub user_token: Box<Account<'info, TokenAccount>>,
+    #[account(mut, address = vault.token_a_vault)]
+    pub token_a_vault: Box<Account<'info, TokenAccount>>,
+    #[account(mut, address = vault.token_b_vault)]
+    pub token_b_vault: Box<Account<'info, TokenAccount>>,
+    #[account(mut, address = whirlpool.token_vault_a)]
+    pub pool_token_vault_a: Box<Account<'info, TokenAccount>>,
+    #[account(mut, address = whirlpool.token_vault_b)]
+    pub pool_token_vault_b: Box<Account<'info, TokenAccount>>,
+
+    // Tick arrays the swap walks through, starting at the current tick
+    #[account(mut, has_one = whirlpool)]
+    pub tick_array_0: AccountLoader<'info, TickArray>,
+    #[account(mut, has_one = whirlpool)]
+    pub tick_array_1: AccountLoader<'info, TickArray>,
+    #[account(mut, has_one = whirlpool)]
+    pub tick_array_2: AccountLoader<'info, TickArray>,
+    /// CHECK: checked by the whirlpool program
+    #[account(
+        seeds = [b"oracle".as_ref(), whirlpool.key().as_ref()],
+        bump,
+        seeds::program = whirlpool_program.key(),
+    )]
+    pub oracle: UncheckedAccount<'info>,
+
+    #[account(mut, address = vault.share_mint)]
+    pub share_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        constraint = user_share.mint == vault.share_mint,
+        constraint = user_share.owner == user.key(),
+    )]
+    pub user_share: Box<Account<'info, TokenAccount>>,
+    // Holds the dead shares minted by the first deposit, nothing can move them out
+    #[account(
+        mut,
+        seeds = [
+            b"dead_shares".as_ref(),
+            vault.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub dead_shares: Box<Account<'info, TokenAccount>>,
+    #[account(mut, address = vault.fee_recipient)]
+    pub fee_recipient: Option<Box<Account<'info, TokenAccount>>>,
+
+    /// CHECK: price feed recorded on the vault, only needed in oracle pricing modes
+    #[account(address = vault.token_a_price_feed)]
+    pub token_a_price_feed: Option<UncheckedAccount<'info>>,
+    /// CHECK: price feed recorded on the vault, only needed in oracle pricing modes
+    #[account(address = vault.token_b_price_feed)]
+    pub token_b_price_feed: Option<UncheckedAccount<'info>>,
+
+    #[account(
+        seeds = [
+            b"observation".as_ref(),
+            whirlpool.key().as_ref(),
+        ],
+        bump,
+        has_one = vault,
+    )]
+    pub observation_state: Option<Box<Account<'info, ObservationState>>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"reward_state".as_ref(),
+            whirlpool.key().as_ref(),
+        ],
+        bump,
+        has_one = vault,
+    )]
+    pub reward_state: Box<Account<'info, RewardState>>,
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + UserRewardCheckpoint::LEN,
+        seeds = [
+            b"reward_checkpoint".as_ref(),
+            vault.key().as_ref(),
+            user.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub user_reward_checkpoint: Box<Account<'info, UserRewardCheckpoint>>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+// Deposits a single token and swaps the fraction the position needs of the
+// other one through the whirlpool. Shares are minted on the value the vault
+// actually received after the swap, so the depositor rather than the existing
+// holders pays its fee and price impact.
+pub fn deposit_single(
+    ctx: Context<DepositSingle>,
+    amount: u64,
+    min_shares_out: u64,
+    max_slippage_bps: u16,
+) -> Result<()> {
+    require!(!ctx.accounts.vault.paused, VaultError::VaultPaused);
+    // Shares are minted on the value received, not pro rata on the ratio the vault holds
+    require!(
+        ctx.accounts.vault.deposit_mode == DepositMode::Valued,
+        VaultError::ProportionalDepositsOnly
+    );
+
+    // Stream rewards to the current holders before the share supply changes
+    ctx.accounts.reward_state.update(Clock::get()?.unix_timestamp)?;
+    let user_reward_checkpoint = &mut ctx.accounts.user_reward_checkpoint;
+    user_reward_checkpoint.vault = ctx.accounts.vault.key();
+    user_reward_checkpoint.user = ctx.accounts.user.key();
+    user_reward_checkpoint.settle(&ctx.accounts.reward_state, ctx.accounts.user_share.amount)?;
+
+    // Leaving the position out would undervalue the vault and over-mint shares
+    require!(
+        ctx.accounts.position.is_some() || ctx.accounts.vault.position == Pubkey::default(),
+        VaultError::MissingPosition
+    );
+
+    let vault = &ctx.accounts.vault;
+    let total_shares = ctx.accounts.share_mint.supply;
+    let whirlpool = &ctx.accounts.whirlpool;
+    let is_token_a = ctx.accounts.user_token.mint == vault.token_a_mint;
+    let (amount_a, amount_b) = if is_token_a { (amount, 0) } else { (0, amount) };
+    // Get the tick index and sqrt_price the vault is valued at
+    let (current_tick_index, sqrt_price) = get_valuation_price(
+        vault,
+        whirlpool,
+        ctx.accounts.observation_state.as_deref(),
+    )?;
+
+    // Calculate total value including tokens in Whirlpool position
+    let (total_token_a_amount, total_token_b_amount) = if let Some(position) = &ctx.accounts.position {
+        calculate_total_tokens(
+            ctx.accounts.token_a_vault.amount,
+            ctx.accounts.token_b_vault.amount,
+            position.liquidity,
+            current_tick_index,
+            sqrt_price,
+            vault.lower_tick,
+            vault.upper_tick,
+        )?
+    } else {
+        (ctx.accounts.token_a_vault.amount, ctx.accounts.token_b_vault.amount)
+    };
+
+    let oracle_prices = PriceSource::new(
+        vault.pricing_mode,
+        ctx.accounts.token_a_price_feed.as_ref(),
+        ctx.accounts.token_b_price_feed.as_ref(),
+    )?
+    .token_prices(vault)?;
+
+    // Refuse to mint or swap against a pool pushed away from the reference price
+    check_price_deviation(
+        get_reference_price_x64(vault, sqrt_price, oracle_prices)?,
+        sqrt_price_to_price_x64(whirlpool.sqrt_price)?,
+        vault.max_price_deviation_bps,
+    )?;
+
+    if vault.pricing_mode == PricingMode::Whirlpool {
+        let tick_deviation = current_tick_index.abs_diff(vault.reference_tick);
+        require!(tick_deviation <= vault.max_tick_deviation, VaultError::TickDeviationTooHigh);
+    }
+
+    let total_value = calculate_vault_value(total_token_a_amount, total_token_b_amount, oracle_prices, sqrt_price)?;
+    let decimal = if oracle_prices.is_some() {
+        vault.token_a_decimal.max(vault.token_b_decimal)
+    } else {
+        vault.token_b_decimal
+    };
+
+    // Fraction of the deposit to swap into the other token
+    let swap = calculate_swap_to_ratio(
+        amount_a,
+        amount_b,
+        whirlpool.tick_current_index,
+        whirlpool.sqrt_price,
+        vault.lower_tick,
+        vault.upper_tick,
+    )?;
+    // The depositor's own bound applies on top of the vault's deviation limit
+    let swap_threshold = match swap {
+        Some((amount_in, a_to_b)) => calculate_min_swap_output(
+            amount_in,
+            a_to_b,
+            whirlpool.sqrt_price,
+            max_slippage_bps.min(vault.max_price_deviation_bps),
+        )?,
+        None => 0,
+    };
+    let (balance_a_before, balance_b_before) = (ctx.accounts.token_a_vault.amount, ctx.accounts.token_b_vault.amount);
+
+    // Accrue management and performance fees on the vault as it stood before this deposit
+    let fee_shares = accrue_fees(
+        &mut ctx.accounts.vault,
+        total_value,
+        total_shares,
+        Clock::get()?.unix_timestamp,
+    )?;
+    let whirlpool_key = ctx.accounts.whirlpool.key();
+    let signer_seeds: &[&[&[u8]]] = &[&[b"vault", whirlpool_key.as_ref(), &[ctx.accounts.vault.bump]]];
+    if fee_shares > 0 {
+        let fee_recipient = ctx.accounts.fee_recipient.as_ref().ok_or(VaultError::MissingFeeRecipient)?;
+        mint_shares(
+            ctx.accounts.token_program.to_account_info(),
+            ctx.accounts.share_mint.to_account_info(),
+            fee_recipient.to_account_info(),
+            ctx.accounts.vault.to_account_info(),
+            signer_seeds,
+            fee_shares,
+        )?;
+    }
+    let total_shares = total_shares
+        .checked_add(fee_shares)
+        .ok_or(VaultError::ArithmeticOverflow)?;
+
+    token::transfer(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.user_token.to_account_info(),
+                to: if is_token_a {
+                    ctx.accounts.token_a_vault.to_account_info()
+                } else {
+                    ctx.accounts.token_b_vault.to_account_info()
+                },
+                authority: ctx.accounts.user.to_account_info(),
+            },
+        ),
+        amount,
+    )?;
+
+    if let Some((amount_in, a_to_b)) = swap {
+        msg!("CPI: whirlpool swap instruction");
+        whirlpool_cpi::cpi::swap(
+            CpiContext::new_with_signer(
+                ctx.accounts.whirlpool_program.to_account_info(),
+                whirlpool_cpi::cpi::accounts::Swap {
+                    token_program: ctx.accounts.token_program.to_account_info(),
+                    token_authority: ctx.accounts.vault.to_account_info(),
+                    whirlpool: ctx.accounts.whirlpool.to_account_info(),
+                    token_owner_account_a: ctx.accounts.token_a_vault.to_account_info(),
+                    token_vault_a: ctx.accounts.pool_token_vault_a.to_account_info(),
+                    token_owner_account_b: ctx.accounts.token_b_vault.to_account_info(),
+                    token_vault_b: ctx.accounts.pool_token_vault_b.to_account_info(),
+                    tick_array_0: ctx.accounts.tick_array_0.to_account_info(),
+                    tick_array_1: ctx.accounts.tick_array_1.to_account_info(),
+                    tick_array_2: ctx.accounts.tick_array_2.to_account_info(),
+                    oracle: ctx.accounts.oracle.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            amount_in,
+            swap_threshold,
+            if a_to_b { MIN_SQRT_PRICE } else { MAX_SQRT_PRICE },
+            true,
+            a_to_b,
+        )?;
+    }
+
+    // Value what the vault received, never more than what was deposited
+    ctx.accounts.token_a_vault.reload()?;
+    ctx.accounts.token_b_vault.reload()?;
+    let received_a = ctx.accounts.token_a_vault.amount
+        .checked_sub(balance_a_before)
+        .ok_or(VaultError::ArithmeticOverflow)?;
+    let received_b = ctx.accounts.token_b_vault.amount
+        .checked_sub(balance_b_before)
+        .ok_or(VaultError::ArithmeticOverflow)?;
+    let deposit_value = calculate_vault_value(amount_a, amount_b, oracle_prices, sqrt_price)?
+        .min(calculate_vault_value(received_a, received_b, oracle_prices, sqrt_price)?);
+
+    let share_amount = calculate_shares(
+        deposit_value,
+        total_value,
+        total_shares,
+        decimal,
+    )?;
+
+    // The first deposit locks dead shares in the vault, so donating to an empty
+    // vault cannot inflate the share price against the next depositor
+    let (dead_share_amount, share_amount) = split_dead_shares(share_amount, total_shares)?;
+
+    // The entry fee is taken out of the minted shares
+    let deposit_fee_shares = calculate_fee_amount(share_amount, ctx.accounts.vault.deposit_fee_bps, Rounding::Up)?;
+    let user_shares = share_amount - deposit_fee_shares;
+    require!(user_shares >= min_shares_out, VaultError::SlippageExceeded);
+
+    if dead_share_amount > 0 {
+        token::mint_to(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::MintTo {
+                    mint: ctx.accounts.share_mint.to_account_info(),
+                    to: ctx.accounts.dead_shares.to_account_info(),
+                    authority: ctx.accounts.vault.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            dead_share_amount,
+        )?;
+    }
+
+    if deposit_fee_shares > 0 {
+        let fee_recipient = ctx.accounts.fee_recipient.as_ref().ok_or(VaultError::MissingFeeRecipient)?;
+        mint_shares(
+            ctx.accounts.token_program.to_account_info(),
+            ctx.accounts.share_mint.to_account_info(),
+            fee_recipient.to_account_info(),
+            ctx.accounts.vault.to_account_info(),
+            signer_seeds,
+            deposit_fee_shares,
+        )?;
+    }
+
+    mint_shares(
+        ctx.accounts.token_program.to_account_info(),
+        ctx.accounts.share_mint.to_account_info(),
+        ctx.accounts.user_share.to_account_info(),
+        ctx.accounts.vault.to_account_info(),
+        signer_seeds,
+        user_shares,
+    )?;
+
+    ctx.accounts.user_share.reload()?;
+    ctx.accounts.reward_state.record_shares(&mut ctx.accounts.user_reward_checkpoint, ctx.accounts.user_share.amount)?;
+
+    Ok(())
+}
@@ -0,0 +1,46 @@
+use anchor_lang::prelude::*;
+use whirlpool_cpi::state::Whirlpool;
+use crate::{
+    errors::VaultError,
+    events::ChangeExecuted,
+    state::{PendingChange, Vault},
+};
+
+#[derive(Accounts)]
+pub struct ExecuteChange<'info> {
+    #[account(mut, constraint = admin.key() == vault.admin)]
+    pub admin: Signer<'info>,
+    
+    pub whirlpool: Box<Account<'info, Whirlpool>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"vault".as_ref(),
+            whirlpool.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub vault: Box<Account<'info, Vault>>,
+
+    #[account(mut, has_one = vault, close = admin)]
+    pub pending_change: Box<Account<'info, PendingChange>>,
+}
+
+pub fn execute_change(
+    ctx: Context<ExecuteChange>,
+) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let pending_change = &ctx.accounts.pending_change;
+    require!(now >= pending_change.executable_at, VaultError::ChangeNotReady);
+
+    let vault = &mut ctx.accounts.vault;
+    pending_change.change.apply(vault, ctx.accounts.whirlpool.tick_spacing, now)?;
+
+    emit!(ChangeExecuted {
+        vault: vault.key(),
+        id: pending_change.id,
+        change: pending_change.change,
+    });
+    Ok(())
+}
@@ -1,7 +1,7 @@
 use anchor_lang::prelude::*;
 use anchor_spl::token::{self, Token, TokenAccount, Mint};
 use whirlpool_cpi::state::Whirlpool;
-use crate::state::Vault;
+use crate::{state::{DepositMode, ObservationState, PricingMode, RewardState, Vault, DEFAULT_MAX_POSITION_WIDTH, DEFAULT_MAX_PRICE_DEVIATION_BPS, SHARE_DECIMALS}, util::PriceSource};
 
 #[derive(Accounts)]
 pub struct InitializeVault<'info> {
@@ -19,6 +19,30 @@ pub struct InitializeVault<'info> {
         space = 8 + Vault::LEN,
     )]
     pub vault: Box<Account<'info, Vault>>,
+
+    #[account(
+        init,
+        seeds = [
+            b"observation".as_ref(),
+            whirlpool.key().as_ref(),
+        ],
+        bump,
+        payer = admin,
+        space = 8 + ObservationState::LEN,
+    )]
+    pub observation_state: Box<Account<'info, ObservationState>>,
+
+    #[account(
+        init,
+        seeds = [
+            b"reward_state".as_ref(),
+            whirlpool.key().as_ref(),
+        ],
+        bump,
+        payer = admin,
+        space = 8 + RewardState::LEN,
+    )]
+    pub reward_state: Box<Account<'info, RewardState>>,
     
     pub whirlpool: Box<Account<'info, Whirlpool>>,
     #[account(constraint = token_a_mint.key() == whirlpool.token_mint_a)]
@@ -39,11 +63,34 @@ pub struct InitializeVault<'info> {
     #[account(
         init,
         payer = admin,
-        mint::decimals = 6,
+        mint::decimals = SHARE_DECIMALS,
         mint::authority = vault,
         mint::freeze_authority = vault,
     )]
     pub share_mint: Account<'info, Mint>,
+    // Vault-owned share account the first deposit locks the dead shares in
+    #[account(
+        init,
+        seeds = [
+            b"dead_shares".as_ref(),
+            vault.key().as_ref(),
+        ],
+        bump,
+        payer = admin,
+        token::mint = share_mint,
+        token::authority = vault,
+    )]
+    pub dead_shares: Account<'info, TokenAccount>,
+
+    #[account(token::mint = token_a_mint)]
+    pub treasury_token_a: Account<'info, TokenAccount>,
+    #[account(token::mint = token_b_mint)]
+    pub treasury_token_b: Account<'info, TokenAccount>,
+
+    /// CHECK: validated against the pricing mode's oracle in the handler, only needed in oracle pricing modes
+    pub token_a_price_feed: Option<UncheckedAccount<'info>>,
+    /// CHECK: validated against the pricing mode's oracle in the handler, only needed in oracle pricing modes
+    pub token_b_price_feed: Option<UncheckedAccount<'info>>,
     
     #[account(address = token::ID)]
     pub token_program: Program<'info, Token>,
@@ -53,25 +100,63 @@ pub struct InitializeVault<'info> {
 
 pub fn initialize_vault(
     ctx: Context<InitializeVault>,
-    token_a_price: u64,
-    token_b_price: u64,
     lower_tick: i32,
     upper_tick: i32,
+    pricing_mode: PricingMode,
+    max_price_age: u64,
+    max_confidence_bps: u16,
+    max_tick_deviation: u32,
+    twap_window: u64,
 ) -> Result<()> {
+    let price_source = PriceSource::new(
+        pricing_mode,
+        ctx.accounts.token_a_price_feed.as_ref(),
+        ctx.accounts.token_b_price_feed.as_ref(),
+    )?;
+    // Make sure both feeds are readable by the chosen backend
+    price_source.load_prices()?;
+    let (token_a_price_feed, token_b_price_feed) = price_source.price_feeds();
+
     let vault = &mut ctx.accounts.vault;
     vault.admin = ctx.accounts.admin.key();
+    vault.operator = ctx.accounts.admin.key();
+    vault.fee_manager = ctx.accounts.admin.key();
+    vault.pauser = ctx.accounts.admin.key();
     vault.whirlpool = ctx.accounts.whirlpool.key();
     vault.token_a_mint = ctx.accounts.token_a_mint.key();
     vault.token_b_mint = ctx.accounts.token_b_mint.key();
     vault.token_a_vault = ctx.accounts.token_a_vault.key();
     vault.token_b_vault = ctx.accounts.token_b_vault.key();
     vault.share_mint = ctx.accounts.share_mint.key();
-    vault.token_a_price = token_a_price;
-    vault.token_b_price = token_b_price;
+    vault.pricing_mode = pricing_mode;
+    vault.deposit_mode = DepositMode::Valued;
+    vault.token_a_price_feed = token_a_price_feed;
+    vault.token_b_price_feed = token_b_price_feed;
+    vault.max_price_age = max_price_age;
+    vault.max_confidence_bps = max_confidence_bps;
+    vault.reference_tick = ctx.accounts.whirlpool.tick_current_index;
+    vault.max_tick_deviation = max_tick_deviation;
+    vault.twap_window = twap_window;
+    vault.max_price_deviation_bps = DEFAULT_MAX_PRICE_DEVIATION_BPS;
+    vault.last_fee_accrual = Clock::get()?.unix_timestamp;
+    vault.treasury_token_a = ctx.accounts.treasury_token_a.key();
+    vault.treasury_token_b = ctx.accounts.treasury_token_b.key();
+    vault.max_position_width = DEFAULT_MAX_POSITION_WIDTH;
     vault.token_a_decimal = ctx.accounts.token_a_mint.decimals;
     vault.token_b_decimal = ctx.accounts.token_b_mint.decimals;
     vault.lower_tick = lower_tick;
     vault.upper_tick = upper_tick;
     vault.bump = ctx.bumps.vault;
+
+    // Seed the buffer with the pool state at creation
+    let whirlpool = &ctx.accounts.whirlpool;
+    let observation_state = &mut ctx.accounts.observation_state;
+    observation_state.vault = vault.key();
+    observation_state.whirlpool = whirlpool.key();
+    observation_state.record(Clock::get()?.slot, whirlpool.tick_current_index)?;
+
+    let reward_state = &mut ctx.accounts.reward_state;
+    reward_state.vault = vault.key();
+    reward_state.last_update = Clock::get()?.unix_timestamp;
     Ok(())
 }
\ No newline at end of file
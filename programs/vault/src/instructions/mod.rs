@@ -1,21 +1,63 @@
 pub mod initialize_vault;
 pub mod deposit;
+pub mod deposit_single;
 pub mod withdraw;
+pub mod withdraw_single;
 pub mod open_position;
 pub mod add_liquidity;
 pub mod close_position;
 pub mod remove_liquidity;
 pub mod collect_fees;
+pub mod compound;
+pub mod rebalance;
+pub mod swap_to_ratio;
+pub mod collect_rewards;
+pub mod claim_rewards;
 pub mod update_ticks;
-pub mod update_prices;
+pub mod update_price_config;
+pub mod update_reference_tick;
+pub mod record_observation;
+pub mod update_fee_config;
+pub mod update_fee_schedule;
+pub mod update_deposit_mode;
+pub mod update_keeper_config;
+pub mod update_rebalance_policy;
+pub mod update_roles;
+pub mod set_paused;
+pub mod propose_admin;
+pub mod accept_admin;
+pub mod schedule_change;
+pub mod execute_change;
+pub mod cancel_change;
 
 pub use initialize_vault::*;
 pub use deposit::*;
+pub use deposit_single::*;
 pub use withdraw::*;
+pub use withdraw_single::*;
 pub use open_position::*;
 pub use add_liquidity::*;
 pub use close_position::*;
 pub use remove_liquidity::*;
 pub use collect_fees::*;
+pub use compound::*;
+pub use rebalance::*;
+pub use swap_to_ratio::*;
+pub use collect_rewards::*;
+pub use claim_rewards::*;
 pub use update_ticks::*;
-pub use update_prices::*;
\ No newline at end of file
+pub use update_price_config::*;
+pub use update_reference_tick::*;
+pub use record_observation::*;
+pub use update_fee_config::*;
+pub use update_fee_schedule::*;
+pub use update_deposit_mode::*;
+pub use update_keeper_config::*;
+pub use update_rebalance_policy::*;
+pub use update_roles::*;
+pub use set_paused::*;
+pub use propose_admin::*;
+pub use accept_admin::*;
+pub use schedule_change::*;
+pub use execute_change::*;
+pub use cancel_change::*;
\ No newline at end of file
@@ -2,7 +2,7 @@ use anchor_lang::prelude::*;
 use anchor_spl::{token::{self, Token}, associated_token::AssociatedToken};
 use whirlpool_cpi::{self, state::*, program::Whirlpool as WhirlpoolProgram};
 
-use crate::state::Vault;
+use crate::{errors::VaultError, state::Vault};
 
 #[derive(Accounts)]
 pub struct OpenPosition<'info> {
@@ -10,11 +10,12 @@ pub struct OpenPosition<'info> {
 
   #[account(
     mut,
-    constraint = funder.key() == vault.admin
+    constraint = funder.key() == vault.operator
   )]
   pub funder: Signer<'info>,
 
   #[account(
+    mut,
     seeds = [
         b"vault".as_ref(),
         whirlpool.key().as_ref(),
@@ -46,6 +47,9 @@ pub vault: Box<Account<'info, Vault>>,
 pub fn open_position(
     ctx: Context<OpenPosition>,
 ) -> Result<()> {
+    // Shares are valued against a single position, recorded on the vault
+    require!(ctx.accounts.vault.position == Pubkey::default(), VaultError::PositionAlreadyOpen);
+
     let cpi_program = ctx.accounts.whirlpool_program.to_account_info();
 
   let cpi_accounts = whirlpool_cpi::cpi::accounts::OpenPosition {
@@ -72,5 +76,6 @@ pub fn open_position(
     ctx.accounts.vault.upper_tick,
   )?;
 
+  ctx.accounts.vault.position = ctx.accounts.position.key();
   Ok(())
 }
@@ -0,0 +1,38 @@
+use anchor_lang::prelude::*;
+use whirlpool_cpi::state::Whirlpool;
+use crate::{events::AdminProposed, state::Vault};
+
+#[derive(Accounts)]
+pub struct ProposeAdmin<'info> {
+    #[account(mut, constraint = admin.key() == vault.admin)]
+    pub admin: Signer<'info>,
+    
+    pub whirlpool: Box<Account<'info, Whirlpool>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"vault".as_ref(),
+            whirlpool.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub vault: Box<Account<'info, Vault>>,
+}
+
+// Ownership only moves once the proposed admin accepts, so a mistyped address
+// cannot take the vault. Proposing the default pubkey cancels a pending transfer.
+pub fn propose_admin(
+    ctx: Context<ProposeAdmin>,
+    new_admin: Pubkey,
+) -> Result<()> {
+    let vault = &mut ctx.accounts.vault;
+    vault.pending_admin = new_admin;
+
+    emit!(AdminProposed {
+        vault: vault.key(),
+        admin: vault.admin,
+        pending_admin: new_admin,
+    });
+    Ok(())
+}
@@ -0,0 +1,386 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{token::{self, Mint, Token, TokenAccount}, associated_token::AssociatedToken};
+use whirlpool_cpi::{self, state::*, program::Whirlpool as WhirlpoolProgram};
+
+use crate::{
+  errors::VaultError,
+  instructions::PositionRewards,
+  state::{ObservationState, RewardLedger, RewardState, Vault},
+  util::{
+    calculate_max_liquidity, calculate_min_swap_output_at_price, calculate_swap_to_ratio, calculate_target_ticks,
+    check_price_deviation, get_reference_price_x64, get_valuation_price, is_out_of_range, sqrt_price_to_price_x64,
+    validate_position_ticks, PriceSource, MAX_SQRT_PRICE, MIN_SQRT_PRICE,
+  },
+};
+
+// Moves all liquidity from the current position into a new one centred on the
+// current tick, in a single transaction, swapping the idle balances into the
+// ratio the new range needs on the way. Permissionless, but only allowed when
+// the vault's rebalance policy says it is due. The position's rewards are
+// collected on the way, a position with rewards owed cannot be closed.
+#[derive(Accounts)]
+pub struct Rebalance<'info> {
+  // Funds the new position and receives the rent of the closed one
+  #[account(mut)]
+  pub caller: Signer<'info>,
+
+  pub whirlpool_program: Program<'info, WhirlpoolProgram>,
+
+  #[account(mut, constraint = whirlpool.key() == vault.whirlpool)]
+  pub whirlpool: Box<Account<'info, Whirlpool>>,
+
+  #[account(
+    mut,
+    seeds = [
+        b"vault".as_ref(),
+        whirlpool.key().as_ref(),
+    ],
+    bump,
+  )]
+  pub vault: Box<Account<'info, Vault>>,
+
+  #[account(mut, has_one = whirlpool, address = vault.position)]
+  pub position: Box<Account<'info, Position>>,
+  #[account(mut, address = position.position_mint)]
+  pub position_mint: Box<Account<'info, Mint>>,
+  #[account(mut,
+      constraint = position_token_account.mint == position.position_mint,
+      constraint = position_token_account.owner == vault.key(),
+      constraint = position_token_account.amount == 1
+  )]
+  pub position_token_account: Box<Account<'info, TokenAccount>>,
+  #[account(mut, has_one = whirlpool)]
+  pub tick_array_lower: AccountLoader<'info, TickArray>,
+  #[account(mut, has_one = whirlpool)]
+  pub tick_array_upper: AccountLoader<'info, TickArray>,
+
+  /// CHECK: init by whirlpool
+  #[account(mut)]
+  pub new_position: UncheckedAccount<'info>,
+  #[account(mut)]
+  pub new_position_mint: Signer<'info>,
+  /// CHECK: init by whirlpool
+  #[account(mut)]
+  pub new_position_token_account: UncheckedAccount<'info>,
+  #[account(mut, has_one = whirlpool)]
+  pub new_tick_array_lower: AccountLoader<'info, TickArray>,
+  #[account(mut, has_one = whirlpool)]
+  pub new_tick_array_upper: AccountLoader<'info, TickArray>,
+
+  #[account(mut, address = vault.token_a_vault)]
+  pub token_vault_a: Box<Account<'info, TokenAccount>>,
+  #[account(mut, address = whirlpool.token_vault_a)]
+  pub pool_token_vault_a: Box<Account<'info, TokenAccount>>,
+
+  #[account(mut, address = vault.token_b_vault)]
+  pub token_vault_b: Box<Account<'info, TokenAccount>>,
+  #[account(mut, address = whirlpool.token_vault_b)]
+  pub pool_token_vault_b: Box<Account<'info, TokenAccount>>,
+
+  // Tick arrays the swap into the new ratio walks through, starting at the current tick
+  #[account(mut, has_one = whirlpool)]
+  pub tick_array_0: AccountLoader<'info, TickArray>,
+  #[account(mut, has_one = whirlpool)]
+  pub tick_array_1: AccountLoader<'info, TickArray>,
+  #[account(mut, has_one = whirlpool)]
+  pub tick_array_2: AccountLoader<'info, TickArray>,
+  /// CHECK: checked by the whirlpool program
+  #[account(
+    seeds = [b"oracle".as_ref(), whirlpool.key().as_ref()],
+    bump,
+    seeds::program = whirlpool_program.key(),
+  )]
+  pub oracle: UncheckedAccount<'info>,
+
+  /// CHECK: price feed recorded on the vault, only needed in oracle pricing modes
+  #[account(address = vault.token_a_price_feed)]
+  pub token_a_price_feed: Option<UncheckedAccount<'info>>,
+  /// CHECK: price feed recorded on the vault, only needed in oracle pricing modes
+  #[account(address = vault.token_b_price_feed)]
+  pub token_b_price_feed: Option<UncheckedAccount<'info>>,
+
+  #[account(
+    seeds = [
+        b"observation".as_ref(),
+        whirlpool.key().as_ref(),
+    ],
+    bump,
+    has_one = vault,
+  )]
+  pub observation_state: Option<Box<Account<'info, ObservationState>>>,
+
+  #[account(
+    init_if_needed,
+    payer = caller,
+    space = 8 + RewardLedger::LEN,
+    seeds = [
+        b"reward_ledger".as_ref(),
+        whirlpool.key().as_ref(),
+    ],
+    bump,
+  )]
+  pub reward_ledger: Box<Account<'info, RewardLedger>>,
+
+  #[account(
+    mut,
+    seeds = [
+        b"reward_state".as_ref(),
+        whirlpool.key().as_ref(),
+    ],
+    bump,
+    has_one = vault,
+  )]
+  pub reward_state: Box<Account<'info, RewardState>>,
+
+  #[account(address = token::ID)]
+  pub token_program: Program<'info, Token>,
+  pub system_program: Program<'info, System>,
+  pub rent: Sysvar<'info, Rent>,
+  pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+// remaining_accounts carries the reward accounts laid out as for collect_rewards
+pub fn rebalance<'info>(
+  ctx: Context<'_, '_, '_, 'info, Rebalance<'info>>,
+) -> Result<()> {
+  let vault = &ctx.accounts.vault;
+  let whirlpool = &ctx.accounts.whirlpool;
+  let now = Clock::get()?.unix_timestamp;
+  require!(!vault.paused, VaultError::VaultPaused);
+  require!(
+    is_out_of_range(whirlpool.tick_current_index, vault.lower_tick, vault.upper_tick, vault.rebalance_threshold_ticks),
+    VaultError::RebalanceNotDue
+  );
+  require!(
+    now >= vault.last_rebalance.saturating_add(vault.min_rebalance_interval as i64),
+    VaultError::RebalanceNotDue
+  );
+
+  // A keeper must not be able to push the pool to trigger a rebalance at a manipulated price
+  let (_, valuation_sqrt_price) = get_valuation_price(
+    vault,
+    whirlpool,
+    ctx.accounts.observation_state.as_deref(),
+  )?;
+  let oracle_prices = PriceSource::new(
+    vault.pricing_mode,
+    ctx.accounts.token_a_price_feed.as_ref(),
+    ctx.accounts.token_b_price_feed.as_ref(),
+  )?
+  .token_prices(vault)?;
+  let reference_price_x64 = get_reference_price_x64(vault, valuation_sqrt_price, oracle_prices)?;
+  check_price_deviation(
+    reference_price_x64,
+    sqrt_price_to_price_x64(whirlpool.sqrt_price)?,
+    vault.max_price_deviation_bps,
+  )?;
+
+  let (lower_tick, upper_tick) = calculate_target_ticks(
+    whirlpool.tick_current_index,
+    whirlpool.tick_spacing,
+    vault.target_position_width,
+  )?;
+  validate_position_ticks(
+    lower_tick,
+    upper_tick,
+    ctx.accounts.whirlpool.tick_spacing,
+    ctx.accounts.vault.max_position_width,
+  )?;
+
+  let whirlpool_key = ctx.accounts.whirlpool.key();
+  let signer_seeds: &[&[&[u8]]] = &[&[b"vault".as_ref(), whirlpool_key.as_ref(), &[ctx.accounts.vault.bump]]];
+
+  if ctx.accounts.position.liquidity > 0 {
+    msg!("CPI: whirlpool decrease_liquidity instruction");
+    whirlpool_cpi::cpi::decrease_liquidity(
+      CpiContext::new_with_signer(
+        ctx.accounts.whirlpool_program.to_account_info(),
+        whirlpool_cpi::cpi::accounts::ModifyLiquidity {
+          whirlpool: ctx.accounts.whirlpool.to_account_info(),
+          token_program: ctx.accounts.token_program.to_account_info(),
+          position_authority: ctx.accounts.vault.to_account_info(),
+          position: ctx.accounts.position.to_account_info(),
+          position_token_account: ctx.accounts.position_token_account.to_account_info(),
+          token_owner_account_a: ctx.accounts.token_vault_a.to_account_info(),
+          token_owner_account_b: ctx.accounts.token_vault_b.to_account_info(),
+          token_vault_a: ctx.accounts.pool_token_vault_a.to_account_info(),
+          token_vault_b: ctx.accounts.pool_token_vault_b.to_account_info(),
+          tick_array_lower: ctx.accounts.tick_array_lower.to_account_info(),
+          tick_array_upper: ctx.accounts.tick_array_upper.to_account_info(),
+        },
+        signer_seeds,
+      ),
+      ctx.accounts.position.liquidity,
+      0,
+      0,
+    )?;
+  }
+
+  msg!("CPI: whirlpool collect_fees instruction");
+  whirlpool_cpi::cpi::collect_fees(CpiContext::new_with_signer(
+    ctx.accounts.whirlpool_program.to_account_info(),
+    whirlpool_cpi::cpi::accounts::CollectFees {
+      whirlpool: ctx.accounts.whirlpool.to_account_info(),
+      position_authority: ctx.accounts.vault.to_account_info(),
+      position: ctx.accounts.position.to_account_info(),
+      position_token_account: ctx.accounts.position_token_account.to_account_info(),
+      token_owner_account_a: ctx.accounts.token_vault_a.to_account_info(),
+      token_vault_a: ctx.accounts.pool_token_vault_a.to_account_info(),
+      token_owner_account_b: ctx.accounts.token_vault_b.to_account_info(),
+      token_vault_b: ctx.accounts.pool_token_vault_b.to_account_info(),
+      token_program: ctx.accounts.token_program.to_account_info(),
+    },
+    signer_seeds,
+  ))?;
+
+  // decrease_liquidity brought the owed rewards up to date, an empty position
+  // stopped accruing when its liquidity was last removed
+  PositionRewards {
+    program_id: ctx.program_id,
+    payer: ctx.accounts.caller.to_account_info(),
+    whirlpool_program: ctx.accounts.whirlpool_program.to_account_info(),
+    whirlpool: &ctx.accounts.whirlpool,
+    vault: &ctx.accounts.vault,
+    position: ctx.accounts.position.to_account_info(),
+    position_token_account: ctx.accounts.position_token_account.to_account_info(),
+    token_program: ctx.accounts.token_program.to_account_info(),
+    system_program: ctx.accounts.system_program.to_account_info(),
+    rent: &ctx.accounts.rent,
+  }
+  .collect(ctx.remaining_accounts, &mut ctx.accounts.reward_ledger, &mut ctx.accounts.reward_state)?;
+
+  msg!("CPI: whirlpool close_position instruction");
+  whirlpool_cpi::cpi::close_position(CpiContext::new_with_signer(
+    ctx.accounts.whirlpool_program.to_account_info(),
+    whirlpool_cpi::cpi::accounts::ClosePosition {
+      position_authority: ctx.accounts.vault.to_account_info(),
+      receiver: ctx.accounts.caller.to_account_info(),
+      position: ctx.accounts.position.to_account_info(),
+      position_mint: ctx.accounts.position_mint.to_account_info(),
+      position_token_account: ctx.accounts.position_token_account.to_account_info(),
+      token_program: ctx.accounts.token_program.to_account_info(),
+    },
+    signer_seeds,
+  ))?;
+
+  msg!("CPI: whirlpool open_position instruction");
+  whirlpool_cpi::cpi::open_position(
+    CpiContext::new(
+      ctx.accounts.whirlpool_program.to_account_info(),
+      whirlpool_cpi::cpi::accounts::OpenPosition {
+        funder: ctx.accounts.caller.to_account_info(),
+        owner: ctx.accounts.vault.to_account_info(),
+        position: ctx.accounts.new_position.to_account_info(),
+        position_mint: ctx.accounts.new_position_mint.to_account_info(),
+        position_token_account: ctx.accounts.new_position_token_account.to_account_info(),
+        whirlpool: ctx.accounts.whirlpool.to_account_info(),
+        token_program: ctx.accounts.token_program.to_account_info(),
+        system_program: ctx.accounts.system_program.to_account_info(),
+        rent: ctx.accounts.rent.to_account_info(),
+        associated_token_program: ctx.accounts.associated_token_program.to_account_info(),
+      },
+    ),
+    whirlpool_cpi::state::OpenPositionBumps { position_bump: 0 }, // passed bump is no longer used
+    lower_tick,
+    upper_tick,
+  )?;
+
+  let vault = &mut ctx.accounts.vault;
+  vault.lower_tick = lower_tick;
+  vault.upper_tick = upper_tick;
+  vault.position = ctx.accounts.new_position.key();
+  vault.last_rebalance = now;
+
+  // Out of range, the closed position left the vault holding almost only one
+  // token. Swap the excess into the ratio the new range needs, within the same
+  // deviation of the reference price the pool price was just checked against.
+  // The bound is not taken from the pool price, which the caller can move first.
+  ctx.accounts.token_vault_a.reload()?;
+  ctx.accounts.token_vault_b.reload()?;
+  ctx.accounts.whirlpool.reload()?;
+  if let Some((amount_in, a_to_b)) = calculate_swap_to_ratio(
+    ctx.accounts.token_vault_a.amount,
+    ctx.accounts.token_vault_b.amount,
+    ctx.accounts.whirlpool.tick_current_index,
+    ctx.accounts.whirlpool.sqrt_price,
+    lower_tick,
+    upper_tick,
+  )? {
+    let other_amount_threshold = calculate_min_swap_output_at_price(
+      amount_in,
+      a_to_b,
+      reference_price_x64,
+      ctx.accounts.vault.max_price_deviation_bps,
+    )?;
+
+    msg!("CPI: whirlpool swap instruction");
+    whirlpool_cpi::cpi::swap(
+      CpiContext::new_with_signer(
+        ctx.accounts.whirlpool_program.to_account_info(),
+        whirlpool_cpi::cpi::accounts::Swap {
+          token_program: ctx.accounts.token_program.to_account_info(),
+          token_authority: ctx.accounts.vault.to_account_info(),
+          whirlpool: ctx.accounts.whirlpool.to_account_info(),
+          token_owner_account_a: ctx.accounts.token_vault_a.to_account_info(),
+          token_vault_a: ctx.accounts.pool_token_vault_a.to_account_info(),
+          token_owner_account_b: ctx.accounts.token_vault_b.to_account_info(),
+          token_vault_b: ctx.accounts.pool_token_vault_b.to_account_info(),
+          tick_array_0: ctx.accounts.tick_array_0.to_account_info(),
+          tick_array_1: ctx.accounts.tick_array_1.to_account_info(),
+          tick_array_2: ctx.accounts.tick_array_2.to_account_info(),
+          oracle: ctx.accounts.oracle.to_account_info(),
+        },
+        signer_seeds,
+      ),
+      amount_in,
+      other_amount_threshold,
+      if a_to_b { MIN_SQRT_PRICE } else { MAX_SQRT_PRICE },
+      true,
+      a_to_b,
+    )?;
+
+    ctx.accounts.token_vault_a.reload()?;
+    ctx.accounts.token_vault_b.reload()?;
+    ctx.accounts.whirlpool.reload()?;
+  }
+
+  // Put everything the vault holds to work in the new range
+  let quote = calculate_max_liquidity(
+    ctx.accounts.token_vault_a.amount,
+    ctx.accounts.token_vault_b.amount,
+    ctx.accounts.whirlpool.tick_current_index,
+    ctx.accounts.whirlpool.sqrt_price,
+    lower_tick,
+    upper_tick,
+  )?;
+  if quote.liquidity_delta == 0 {
+    msg!("Nothing to deploy in the new range");
+    return Ok(());
+  }
+
+  msg!("CPI: whirlpool increase_liquidity instruction");
+  whirlpool_cpi::cpi::increase_liquidity(
+    CpiContext::new_with_signer(
+      ctx.accounts.whirlpool_program.to_account_info(),
+      whirlpool_cpi::cpi::accounts::ModifyLiquidity {
+        whirlpool: ctx.accounts.whirlpool.to_account_info(),
+        token_program: ctx.accounts.token_program.to_account_info(),
+        position_authority: ctx.accounts.vault.to_account_info(),
+        position: ctx.accounts.new_position.to_account_info(),
+        position_token_account: ctx.accounts.new_position_token_account.to_account_info(),
+        token_owner_account_a: ctx.accounts.token_vault_a.to_account_info(),
+        token_owner_account_b: ctx.accounts.token_vault_b.to_account_info(),
+        token_vault_a: ctx.accounts.pool_token_vault_a.to_account_info(),
+        token_vault_b: ctx.accounts.pool_token_vault_b.to_account_info(),
+        tick_array_lower: ctx.accounts.new_tick_array_lower.to_account_info(),
+        tick_array_upper: ctx.accounts.new_tick_array_upper.to_account_info(),
+      },
+      signer_seeds,
+    ),
+    quote.liquidity_delta,
+    quote.token_max_a,
+    quote.token_max_b,
+  )?;
+
+  Ok(())
+}
@@ -0,0 +1,48 @@
+use anchor_lang::prelude::*;
+use whirlpool_cpi::state::Whirlpool;
+use crate::{errors::VaultError, state::{ObservationState, Vault}};
+
+#[derive(Accounts)]
+pub struct RecordObservation<'info> {
+    #[account(constraint = whirlpool.key() == vault.whirlpool)]
+    pub whirlpool: Box<Account<'info, Whirlpool>>,
+
+    #[account(
+        seeds = [
+            b"vault".as_ref(),
+            whirlpool.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub vault: Box<Account<'info, Vault>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"observation".as_ref(),
+            whirlpool.key().as_ref(),
+        ],
+        bump,
+        has_one = vault,
+    )]
+    pub observation_state: Box<Account<'info, ObservationState>>,
+}
+
+pub fn record_observation(
+    ctx: Context<RecordObservation>,
+) -> Result<()> {
+    let slot = Clock::get()?.slot;
+    let whirlpool = &ctx.accounts.whirlpool;
+    let observation_state = &mut ctx.accounts.observation_state;
+
+    // Spacing records out keeps a swap and swap back around a record from
+    // weighing more than its share of the window, and the buffer from being flushed
+    if let Some(latest) = observation_state.latest() {
+        require!(
+            slot >= latest.slot.saturating_add(ObservationState::min_interval(ctx.accounts.vault.twap_window)),
+            VaultError::ObservationTooRecent
+        );
+    }
+
+    observation_state.record(slot, whirlpool.tick_current_index)
+}
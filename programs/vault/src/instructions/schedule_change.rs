@@ -0,0 +1,80 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::TokenAccount;
+use whirlpool_cpi::state::Whirlpool;
+use crate::{
+    errors::VaultError,
+    events::ChangeScheduled,
+    state::{ParameterChange, PendingChange, Vault},
+};
+
+#[derive(Accounts)]
+pub struct ScheduleChange<'info> {
+    #[account(mut, constraint = admin.key() == vault.admin)]
+    pub admin: Signer<'info>,
+    
+    pub whirlpool: Box<Account<'info, Whirlpool>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"vault".as_ref(),
+            whirlpool.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub vault: Box<Account<'info, Vault>>,
+
+    #[account(
+        init,
+        seeds = [
+            b"pending_change".as_ref(),
+            vault.key().as_ref(),
+            vault.next_change_id.to_le_bytes().as_ref(),
+        ],
+        bump,
+        payer = admin,
+        space = 8 + PendingChange::LEN,
+    )]
+    pub pending_change: Box<Account<'info, PendingChange>>,
+
+    // Only needed to check the recipient of a fee config change
+    #[account(constraint = fee_recipient.mint == vault.share_mint)]
+    pub fee_recipient: Option<Box<Account<'info, TokenAccount>>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+pub fn schedule_change(
+    ctx: Context<ScheduleChange>,
+    change: ParameterChange,
+) -> Result<()> {
+    change.validate(&ctx.accounts.vault, ctx.accounts.whirlpool.tick_spacing)?;
+    if let ParameterChange::FeeConfig { fee_recipient, .. } = change {
+        let fee_recipient_account = ctx.accounts.fee_recipient.as_ref().ok_or(VaultError::MissingFeeRecipient)?;
+        require_keys_eq!(fee_recipient_account.key(), fee_recipient, VaultError::MissingFeeRecipient);
+    }
+
+    let now = Clock::get()?.unix_timestamp;
+    let vault = &mut ctx.accounts.vault;
+    let executable_at = now
+        .checked_add(vault.timelock_delay)
+        .ok_or(VaultError::ArithmeticOverflow)?;
+
+    let pending_change = &mut ctx.accounts.pending_change;
+    pending_change.vault = vault.key();
+    pending_change.id = vault.next_change_id;
+    pending_change.change = change;
+    pending_change.scheduled_at = now;
+    pending_change.executable_at = executable_at;
+    vault.next_change_id = vault.next_change_id
+        .checked_add(1)
+        .ok_or(VaultError::ArithmeticOverflow)?;
+
+    emit!(ChangeScheduled {
+        vault: vault.key(),
+        id: pending_change.id,
+        change,
+        executable_at,
+    });
+    Ok(())
+}
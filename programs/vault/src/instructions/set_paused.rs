@@ -0,0 +1,30 @@
+use anchor_lang::prelude::*;
+use whirlpool_cpi::state::Whirlpool;
+use crate::state::Vault;
+
+#[derive(Accounts)]
+pub struct SetPaused<'info> {
+    // The owner can always pause or unpause as well
+    #[account(constraint = authority.key() == vault.pauser || authority.key() == vault.admin)]
+    pub authority: Signer<'info>,
+    
+    pub whirlpool: Box<Account<'info, Whirlpool>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"vault".as_ref(),
+            whirlpool.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub vault: Box<Account<'info, Vault>>,
+}
+
+pub fn set_paused(
+    ctx: Context<SetPaused>,
+    paused: bool,
+) -> Result<()> {
+    ctx.accounts.vault.paused = paused;
+    Ok(())
+}
@@ -0,0 +1,152 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Token, TokenAccount};
+use whirlpool_cpi::{self, state::*, program::Whirlpool as WhirlpoolProgram};
+
+use crate::{
+  errors::VaultError,
+  state::{ObservationState, Vault},
+  util::{
+    calculate_min_swap_output, calculate_swap_to_ratio, check_price_deviation, get_reference_price_x64,
+    get_valuation_price, sqrt_price_to_price_x64, PriceSource,
+  },
+};
+
+#[derive(Accounts)]
+pub struct SwapToRatio<'info> {
+  #[account(mut, constraint = operator.key() == vault.operator)]
+  pub operator: Signer<'info>,
+
+  pub whirlpool_program: Program<'info, WhirlpoolProgram>,
+
+  #[account(mut, constraint = whirlpool.key() == vault.whirlpool)]
+  pub whirlpool: Box<Account<'info, Whirlpool>>,
+
+  #[account(
+    seeds = [
+        b"vault".as_ref(),
+        whirlpool.key().as_ref(),
+    ],
+    bump,
+  )]
+  pub vault: Box<Account<'info, Vault>>,
+
+  #[account(mut, address = vault.token_a_vault)]
+  pub token_vault_a: Box<Account<'info, TokenAccount>>,
+  #[account(mut, address = whirlpool.token_vault_a)]
+  pub pool_token_vault_a: Box<Account<'info, TokenAccount>>,
+
+  #[account(mut, address = vault.token_b_vault)]
+  pub token_vault_b: Box<Account<'info, TokenAccount>>,
+  #[account(mut, address = whirlpool.token_vault_b)]
+  pub pool_token_vault_b: Box<Account<'info, TokenAccount>>,
+
+  // Tick arrays the swap walks through, starting at the current tick
+  #[account(mut, has_one = whirlpool)]
+  pub tick_array_0: AccountLoader<'info, TickArray>,
+  #[account(mut, has_one = whirlpool)]
+  pub tick_array_1: AccountLoader<'info, TickArray>,
+  #[account(mut, has_one = whirlpool)]
+  pub tick_array_2: AccountLoader<'info, TickArray>,
+
+  /// CHECK: checked by the whirlpool program
+  #[account(
+    seeds = [b"oracle".as_ref(), whirlpool.key().as_ref()],
+    bump,
+    seeds::program = whirlpool_program.key(),
+  )]
+  pub oracle: UncheckedAccount<'info>,
+
+  /// CHECK: price feed recorded on the vault, only needed in oracle pricing modes
+  #[account(address = vault.token_a_price_feed)]
+  pub token_a_price_feed: Option<UncheckedAccount<'info>>,
+  /// CHECK: price feed recorded on the vault, only needed in oracle pricing modes
+  #[account(address = vault.token_b_price_feed)]
+  pub token_b_price_feed: Option<UncheckedAccount<'info>>,
+
+  #[account(
+    seeds = [
+        b"observation".as_ref(),
+        whirlpool.key().as_ref(),
+    ],
+    bump,
+    has_one = vault,
+  )]
+  pub observation_state: Option<Box<Account<'info, ObservationState>>>,
+
+  #[account(address = token::ID)]
+  pub token_program: Program<'info, Token>,
+}
+
+// Swaps the excess side of the idle balances into the ratio the position needs
+pub fn swap_to_ratio(
+  ctx: Context<SwapToRatio>,
+  max_slippage_bps: u16,
+  sqrt_price_limit: u128,
+) -> Result<()> {
+  let vault = &ctx.accounts.vault;
+  let whirlpool = &ctx.accounts.whirlpool;
+  require!(!vault.paused, VaultError::VaultPaused);
+
+  // Never trade against a pool pushed away from the reference price
+  let (_, valuation_sqrt_price) = get_valuation_price(
+    vault,
+    whirlpool,
+    ctx.accounts.observation_state.as_deref(),
+  )?;
+  let oracle_prices = PriceSource::new(
+    vault.pricing_mode,
+    ctx.accounts.token_a_price_feed.as_ref(),
+    ctx.accounts.token_b_price_feed.as_ref(),
+  )?
+  .token_prices(vault)?;
+  check_price_deviation(
+    get_reference_price_x64(vault, valuation_sqrt_price, oracle_prices)?,
+    sqrt_price_to_price_x64(whirlpool.sqrt_price)?,
+    vault.max_price_deviation_bps,
+  )?;
+
+  let Some((amount_in, a_to_b)) = calculate_swap_to_ratio(
+    ctx.accounts.token_vault_a.amount,
+    ctx.accounts.token_vault_b.amount,
+    whirlpool.tick_current_index,
+    whirlpool.sqrt_price,
+    vault.lower_tick,
+    vault.upper_tick,
+  )? else {
+    msg!("Balances already match the position ratio");
+    return Ok(());
+  };
+
+  let other_amount_threshold = calculate_min_swap_output(amount_in, a_to_b, whirlpool.sqrt_price, max_slippage_bps)?;
+
+  let whirlpool_key = whirlpool.key();
+  let signer_seeds: &[&[&[u8]]] = &[&[b"vault".as_ref(), whirlpool_key.as_ref(), &[vault.bump]]];
+
+  msg!("CPI: whirlpool swap instruction");
+  whirlpool_cpi::cpi::swap(
+    CpiContext::new_with_signer(
+      ctx.accounts.whirlpool_program.to_account_info(),
+      whirlpool_cpi::cpi::accounts::Swap {
+        token_program: ctx.accounts.token_program.to_account_info(),
+        token_authority: ctx.accounts.vault.to_account_info(),
+        whirlpool: ctx.accounts.whirlpool.to_account_info(),
+        token_owner_account_a: ctx.accounts.token_vault_a.to_account_info(),
+        token_vault_a: ctx.accounts.pool_token_vault_a.to_account_info(),
+        token_owner_account_b: ctx.accounts.token_vault_b.to_account_info(),
+        token_vault_b: ctx.accounts.pool_token_vault_b.to_account_info(),
+        tick_array_0: ctx.accounts.tick_array_0.to_account_info(),
+        tick_array_1: ctx.accounts.tick_array_1.to_account_info(),
+        tick_array_2: ctx.accounts.tick_array_2.to_account_info(),
+        oracle: ctx.accounts.oracle.to_account_info(),
+      },
+      signer_seeds,
+    ),
+    amount_in,
+    other_amount_threshold,
+    sqrt_price_limit,
+    true,
+    a_to_b,
+  )?;
+
+  Ok(())
+}
@@ -0,0 +1,38 @@
+use anchor_lang::prelude::*;
+use whirlpool_cpi::state::Whirlpool;
+use crate::{
+    errors::VaultError,
+    state::{DepositMode, ParameterChange, Vault},
+};
+
+#[derive(Accounts)]
+pub struct UpdateDepositMode<'info> {
+    #[account(mut, constraint = admin.key() == vault.admin)]
+    pub admin: Signer<'info>,
+    
+    pub whirlpool: Box<Account<'info, Whirlpool>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"vault".as_ref(),
+            whirlpool.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub vault: Box<Account<'info, Vault>>,
+}
+
+pub fn update_deposit_mode(
+    ctx: Context<UpdateDepositMode>,
+    deposit_mode: DepositMode,
+) -> Result<()> {
+    // Once a timelock is set, changes have to be scheduled through schedule_change
+    require!(ctx.accounts.vault.timelock_delay == 0, VaultError::TimelockActive);
+
+    ParameterChange::DepositMode { deposit_mode }.apply(
+        &mut ctx.accounts.vault,
+        ctx.accounts.whirlpool.tick_spacing,
+        Clock::get()?.unix_timestamp,
+    )
+}
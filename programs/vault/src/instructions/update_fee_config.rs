@@ -0,0 +1,48 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::TokenAccount;
+use whirlpool_cpi::state::Whirlpool;
+use crate::{
+    errors::VaultError,
+    state::{ParameterChange, Vault},
+};
+
+#[derive(Accounts)]
+pub struct UpdateFeeConfig<'info> {
+    #[account(mut, constraint = admin.key() == vault.admin)]
+    pub admin: Signer<'info>,
+    
+    pub whirlpool: Box<Account<'info, Whirlpool>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"vault".as_ref(),
+            whirlpool.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub vault: Box<Account<'info, Vault>>,
+
+    #[account(constraint = fee_recipient.mint == vault.share_mint)]
+    pub fee_recipient: Box<Account<'info, TokenAccount>>,
+}
+
+pub fn update_fee_config(
+    ctx: Context<UpdateFeeConfig>,
+    management_fee_bps: u16,
+    performance_fee_bps: u16,
+) -> Result<()> {
+    // Once a timelock is set, changes have to be scheduled through schedule_change
+    require!(ctx.accounts.vault.timelock_delay == 0, VaultError::TimelockActive);
+
+    ParameterChange::FeeConfig {
+        fee_recipient: ctx.accounts.fee_recipient.key(),
+        management_fee_bps,
+        performance_fee_bps,
+    }
+    .apply(
+        &mut ctx.accounts.vault,
+        ctx.accounts.whirlpool.tick_spacing,
+        Clock::get()?.unix_timestamp,
+    )
+}
@@ -0,0 +1,39 @@
+use anchor_lang::prelude::*;
+use whirlpool_cpi::state::Whirlpool;
+use crate::{
+    errors::VaultError,
+    state::{ParameterChange, Vault},
+};
+
+#[derive(Accounts)]
+pub struct UpdateKeeperConfig<'info> {
+    #[account(mut, constraint = admin.key() == vault.admin)]
+    pub admin: Signer<'info>,
+    
+    pub whirlpool: Box<Account<'info, Whirlpool>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"vault".as_ref(),
+            whirlpool.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub vault: Box<Account<'info, Vault>>,
+}
+
+pub fn update_keeper_config(
+    ctx: Context<UpdateKeeperConfig>,
+    compound_bounty_bps: u16,
+    min_compound_interval: u64,
+) -> Result<()> {
+    // Once a timelock is set, changes have to be scheduled through schedule_change
+    require!(ctx.accounts.vault.timelock_delay == 0, VaultError::TimelockActive);
+
+    ParameterChange::KeeperConfig { compound_bounty_bps, min_compound_interval }.apply(
+        &mut ctx.accounts.vault,
+        ctx.accounts.whirlpool.tick_spacing,
+        Clock::get()?.unix_timestamp,
+    )
+}
@@ -0,0 +1,44 @@
+use anchor_lang::prelude::*;
+use whirlpool_cpi::state::Whirlpool;
+use crate::{errors::VaultError, state::{ParameterChange, Vault}};
+
+#[derive(Accounts)]
+pub struct UpdatePriceConfig<'info> {
+    #[account(mut, constraint = admin.key() == vault.admin)]
+    pub admin: Signer<'info>,
+    
+    pub whirlpool: Box<Account<'info, Whirlpool>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"vault".as_ref(),
+            whirlpool.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub vault: Box<Account<'info, Vault>>,
+}
+
+pub fn update_price_config(
+    ctx: Context<UpdatePriceConfig>,
+    max_price_age: u64,
+    max_confidence_bps: u16,
+    max_tick_deviation: u32,
+    max_price_deviation_bps: u16,
+) -> Result<()> {
+    // Once a timelock is set, changes have to be scheduled through schedule_change
+    require!(ctx.accounts.vault.timelock_delay == 0, VaultError::TimelockActive);
+
+    ParameterChange::PriceConfig {
+        max_price_age,
+        max_confidence_bps,
+        max_tick_deviation,
+        max_price_deviation_bps,
+    }
+    .apply(
+        &mut ctx.accounts.vault,
+        ctx.accounts.whirlpool.tick_spacing,
+        Clock::get()?.unix_timestamp,
+    )
+}
@@ -0,0 +1,47 @@
+use anchor_lang::prelude::*;
+use whirlpool_cpi::state::Whirlpool;
+use crate::{
+    errors::VaultError,
+    state::{ParameterChange, Vault},
+};
+
+#[derive(Accounts)]
+pub struct UpdateRebalancePolicy<'info> {
+    #[account(mut, constraint = admin.key() == vault.admin)]
+    pub admin: Signer<'info>,
+    
+    pub whirlpool: Box<Account<'info, Whirlpool>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"vault".as_ref(),
+            whirlpool.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub vault: Box<Account<'info, Vault>>,
+}
+
+pub fn update_rebalance_policy(
+    ctx: Context<UpdateRebalancePolicy>,
+    max_position_width: u32,
+    target_position_width: u32,
+    rebalance_threshold_ticks: u32,
+    min_rebalance_interval: u64,
+) -> Result<()> {
+    // Once a timelock is set, changes have to be scheduled through schedule_change
+    require!(ctx.accounts.vault.timelock_delay == 0, VaultError::TimelockActive);
+
+    ParameterChange::RebalancePolicy {
+        max_position_width,
+        target_position_width,
+        rebalance_threshold_ticks,
+        min_rebalance_interval,
+    }
+    .apply(
+        &mut ctx.accounts.vault,
+        ctx.accounts.whirlpool.tick_spacing,
+        Clock::get()?.unix_timestamp,
+    )
+}
@@ -3,7 +3,7 @@ use whirlpool_cpi::state::Whirlpool;
 use crate::state::Vault;
 
 #[derive(Accounts)]
-pub struct UpdatePrices<'info> {
+pub struct UpdateReferenceTick<'info> {
     #[account(mut, constraint = admin.key() == vault.admin)]
     pub admin: Signer<'info>,
     
@@ -20,13 +20,11 @@ pub struct UpdatePrices<'info> {
     pub vault: Box<Account<'info, Vault>>,
 }
 
-pub fn update_prices(
-    ctx: Context<UpdatePrices>,
-    token_a_price: u64,
-    token_b_price: u64,
+pub fn update_reference_tick(
+    ctx: Context<UpdateReferenceTick>,
 ) -> Result<()> {
-    let vault = &mut ctx.accounts.vault;
-    vault.token_a_price = token_a_price;
-    vault.token_b_price = token_b_price;
+    // Not timelocked: the reference can only be snapped to the live tick, and a stale
+    // one would otherwise block the price-guarded paths until a scheduled change lands
+    ctx.accounts.vault.reference_tick = ctx.accounts.whirlpool.tick_current_index;
     Ok(())
-}
\ No newline at end of file
+}
@@ -1,6 +1,6 @@
 use anchor_lang::prelude::*;
 use whirlpool_cpi::state::Whirlpool;
-use crate::state::Vault;
+use crate::{errors::VaultError, state::{ParameterChange, Vault}};
 
 #[derive(Accounts)]
 pub struct UpdateTicks<'info> {
@@ -25,8 +25,12 @@ pub fn update_ticks(
     lower_tick: i32,
     upper_tick: i32,
 ) -> Result<()> {
-    let vault = &mut ctx.accounts.vault;
-    vault.lower_tick = lower_tick;
-    vault.upper_tick = upper_tick;
-    Ok(())
+    // Once a timelock is set, changes have to be scheduled through schedule_change
+    require!(ctx.accounts.vault.timelock_delay == 0, VaultError::TimelockActive);
+
+    ParameterChange::Ticks { lower_tick, upper_tick }.apply(
+        &mut ctx.accounts.vault,
+        ctx.accounts.whirlpool.tick_spacing,
+        Clock::get()?.unix_timestamp,
+    )
 }
\ No newline at end of file
@@ -1,17 +1,27 @@
 use anchor_lang::prelude::*;
 use anchor_spl::token::{self, Token, TokenAccount, Mint, Transfer};
-use whirlpool_cpi::state::{Position, Whirlpool};
-use crate::{errors::VaultError, state::Vault, util::{calculate_proportional_amount, calculate_total_tokens}};
+use whirlpool_cpi::{self, program::Whirlpool as WhirlpoolProgram, state::{Position, TickArray, Whirlpool}};
+use crate::{
+    errors::VaultError,
+    state::{ObservationState, RewardState, UserRewardCheckpoint, Vault},
+    util::{
+        accrue_fees, burn_shares, calculate_fee_amount, calculate_proportional_amount, calculate_total_tokens,
+        calculate_vault_value, get_valuation_price, mint_shares, mul_div, PriceSource, Rounding,
+    },
+};
 
 #[derive(Accounts)]
 pub struct Withdraw<'info> {
     #[account(mut)]
     pub user: Signer<'info>,
 
-    #[account(constraint = whirlpool.key() == vault.whirlpool)]
+    pub whirlpool_program: Program<'info, WhirlpoolProgram>,
+
+    #[account(mut, constraint = whirlpool.key() == vault.whirlpool)]
     pub whirlpool: Box<Account<'info, Whirlpool>>,
 
     #[account(
+        mut,
         seeds = [
             b"vault".as_ref(),
             whirlpool.key().as_ref(),
@@ -20,9 +30,27 @@ pub struct Withdraw<'info> {
       )]
     pub vault: Box<Account<'info, Vault>>,
 
-    #[account(has_one = whirlpool)]
-    pub position: Option<Account<'info, Position>>,
-    
+    // Required whenever the vault has a position open
+    #[account(mut, has_one = whirlpool, address = vault.position)]
+    pub position: Option<Box<Account<'info, Position>>>,
+
+    // Needed to pull the user's share of the position's liquidity
+    #[account(
+        constraint = position.as_ref().is_some_and(|position| position_token_account.mint == position.position_mint),
+        constraint = position_token_account.owner == vault.key(),
+        constraint = position_token_account.amount == 1,
+    )]
+    pub position_token_account: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(mut, address = whirlpool.token_vault_a)]
+    pub pool_token_vault_a: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(mut, address = whirlpool.token_vault_b)]
+    pub pool_token_vault_b: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(mut, has_one = whirlpool)]
+    pub tick_array_lower: Option<AccountLoader<'info, TickArray>>,
+    #[account(mut, has_one = whirlpool)]
+    pub tick_array_upper: Option<AccountLoader<'info, TickArray>>,
+
+
     #[account(mut, constraint = user_token_a.mint == whirlpool.token_mint_a)]
     pub user_token_a: Account<'info, TokenAccount>,
     #[account(mut, constraint = user_token_b.mint == whirlpool.token_mint_b)]
@@ -34,31 +62,82 @@ pub struct Withdraw<'info> {
     
     #[account(mut, constraint = share_mint.key() == vault.share_mint)]
     pub share_mint: Account<'info, Mint>,
-    #[account(mut, constraint = user_share.mint == vault.share_mint)]
+    #[account(
+        mut,
+        constraint = user_share.mint == vault.share_mint,
+        constraint = user_share.owner == user.key(),
+    )]
     pub user_share: Account<'info, TokenAccount>,
+    #[account(mut, address = vault.fee_recipient)]
+    pub fee_recipient: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(mut, address = vault.treasury_token_a)]
+    pub treasury_token_a: Box<Account<'info, TokenAccount>>,
+    #[account(mut, address = vault.treasury_token_b)]
+    pub treasury_token_b: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: price feed recorded on the vault, only needed in oracle pricing modes
+    #[account(address = vault.token_a_price_feed)]
+    pub token_a_price_feed: Option<UncheckedAccount<'info>>,
+    /// CHECK: price feed recorded on the vault, only needed in oracle pricing modes
+    #[account(address = vault.token_b_price_feed)]
+    pub token_b_price_feed: Option<UncheckedAccount<'info>>,
+
+    #[account(
+        seeds = [
+            b"observation".as_ref(),
+            whirlpool.key().as_ref(),
+        ],
+        bump,
+        has_one = vault,
+    )]
+    pub observation_state: Option<Box<Account<'info, ObservationState>>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"reward_state".as_ref(),
+            whirlpool.key().as_ref(),
+        ],
+        bump,
+        has_one = vault,
+    )]
+    pub reward_state: Box<Account<'info, RewardState>>,
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + UserRewardCheckpoint::LEN,
+        seeds = [
+            b"reward_checkpoint".as_ref(),
+            vault.key().as_ref(),
+            user.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub user_reward_checkpoint: Box<Account<'info, UserRewardCheckpoint>>,
     
     pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
 }
 
-pub fn withdraw(
-    ctx: Context<Withdraw>,
-    share_amount: u64,
-    token_min_a_amount: u64,
-    token_min_b_amount: u64,
-) -> Result<()> {
-    let vault = &ctx.accounts.vault;
-    let total_shares = ctx.accounts.share_mint.supply;
-    let whirlpool = &ctx.accounts.whirlpool;
-    let position = &ctx.accounts.position;
-    // Get current tick index and sqrt_price from whirlpool
-    let current_tick_index = whirlpool.tick_current_index;
-    let sqrt_price = whirlpool.sqrt_price;
-    
-    // Calculate total value including tokens in Whirlpool position
-    let (total_token_a_amount, total_token_b_amount) = if let Some(position) = position {
+// Vault value at its prices, only needed to accrue fees
+fn vault_value(accounts: &Withdraw) -> Result<u128> {
+    let vault = &accounts.vault;
+    let (current_tick_index, sqrt_price) = get_valuation_price(
+        vault,
+        &accounts.whirlpool,
+        accounts.observation_state.as_deref(),
+    )?;
+    let oracle_prices = PriceSource::new(
+        vault.pricing_mode,
+        accounts.token_a_price_feed.as_ref(),
+        accounts.token_b_price_feed.as_ref(),
+    )?
+    .token_prices(vault)?;
+
+    let (total_token_a_amount, total_token_b_amount) = if let Some(position) = &accounts.position {
         calculate_total_tokens(
-            ctx.accounts.token_a_vault.amount,
-            ctx.accounts.token_b_vault.amount,
+            accounts.token_a_vault.amount,
+            accounts.token_b_vault.amount,
             position.liquidity,
             current_tick_index,
             sqrt_price,
@@ -66,54 +145,176 @@ pub fn withdraw(
             vault.upper_tick,
         )?
     } else {
-        (ctx.accounts.token_a_vault.amount, ctx.accounts.token_b_vault.amount)
+        (accounts.token_a_vault.amount, accounts.token_b_vault.amount)
     };
+    Ok(calculate_vault_value(total_token_a_amount, total_token_b_amount, oracle_prices, sqrt_price)?)
+}
 
-    // Calculate proportional amounts based on total tokens (including in position)
-    let mut token_a_amount = calculate_proportional_amount(
-        total_token_a_amount,
+// Redeems shares for the same fraction of the idle balances and of the
+// position's liquidity. No price is involved, so withdrawals stay open while
+// the oracle is stale or the pool is away from its reference price.
+pub fn withdraw(
+    ctx: Context<Withdraw>,
+    share_amount: u64,
+    token_min_a_amount: u64,
+    token_min_b_amount: u64,
+) -> Result<()> {
+    // Stream rewards to the current holders before the share supply changes
+    ctx.accounts.reward_state.update(Clock::get()?.unix_timestamp)?;
+    let user_reward_checkpoint = &mut ctx.accounts.user_reward_checkpoint;
+    user_reward_checkpoint.vault = ctx.accounts.vault.key();
+    user_reward_checkpoint.user = ctx.accounts.user.key();
+    user_reward_checkpoint.settle(&ctx.accounts.reward_state, ctx.accounts.user_share.amount)?;
+
+    // Redeeming without the position would leave the user's share of it behind
+    require!(
+        ctx.accounts.position.is_some() || ctx.accounts.vault.position == Pubkey::default(),
+        VaultError::MissingPosition
+    );
+
+    // Accrue management and performance fees before pricing the redemption. A
+    // stale oracle or a short observation buffer only defers the accrual to
+    // the next priced instruction.
+    let total_shares = ctx.accounts.share_mint.supply;
+    let fee_shares = match vault_value(&ctx.accounts) {
+        Ok(total_value) => accrue_fees(
+            &mut ctx.accounts.vault,
+            total_value,
+            total_shares,
+            Clock::get()?.unix_timestamp,
+        )?,
+        Err(_) => 0,
+    };
+    let whirlpool_key = ctx.accounts.whirlpool.key();
+    let signer_seeds: &[&[&[u8]]] = &[&[b"vault".as_ref(), whirlpool_key.as_ref(), &[ctx.accounts.vault.bump]]];
+    if fee_shares > 0 {
+        let fee_recipient = ctx.accounts.fee_recipient.as_ref().ok_or(VaultError::MissingFeeRecipient)?;
+        mint_shares(
+            ctx.accounts.token_program.to_account_info(),
+            ctx.accounts.share_mint.to_account_info(),
+            fee_recipient.to_account_info(),
+            ctx.accounts.vault.to_account_info(),
+            signer_seeds,
+            fee_shares,
+        )?;
+    }
+    let total_shares = total_shares
+        .checked_add(fee_shares)
+        .ok_or(VaultError::ArithmeticOverflow)?;
+
+    // The user's fraction of the idle balances
+    let idle_a_amount = calculate_proportional_amount(
+        ctx.accounts.token_a_vault.amount,
         share_amount,
         total_shares,
+        Rounding::Down,
     )?;
-    
-    let mut token_b_amount = calculate_proportional_amount(
-        total_token_b_amount,
+    let idle_b_amount = calculate_proportional_amount(
+        ctx.accounts.token_b_vault.amount,
         share_amount,
         total_shares,
+        Rounding::Down,
     )?;
 
-    if token_a_amount > ctx.accounts.token_a_vault.amount || token_b_amount > ctx.accounts.token_b_vault.amount {
-         // Choose the more limiting token
-        let a_ratio = token_a_amount as f64 / ctx.accounts.token_a_vault.amount as f64;
-        let b_ratio = token_b_amount as f64 / ctx.accounts.token_b_vault.amount as f64;
-         
-        if a_ratio > b_ratio {
-            token_a_amount = ctx.accounts.token_a_vault.amount;
-            token_b_amount = calculate_proportional_amount(
-                total_token_b_amount,
-                token_a_amount,
-                total_token_a_amount,
-            )?;
-        } else {
-            token_b_amount = ctx.accounts.token_b_vault.amount;
-            token_a_amount = calculate_proportional_amount(
-                total_token_a_amount,
-                token_b_amount,
-                total_token_b_amount,
-            )?;
-        }
+    // And of the position's liquidity, pulled out at whatever the pool pays for
+    // it. The fees the position earned stay owed to the vault and are harvested
+    // by collect_fees or compound.
+    let liquidity_amount = match &ctx.accounts.position {
+        Some(position) => mul_div(
+            position.liquidity,
+            share_amount as u128,
+            total_shares as u128,
+            Rounding::Down,
+        )?,
+        None => 0,
+    };
+    let (mut token_a_amount, mut token_b_amount) = (idle_a_amount, idle_b_amount);
+    if liquidity_amount > 0 {
+        let (balance_a_before, balance_b_before) = (ctx.accounts.token_a_vault.amount, ctx.accounts.token_b_vault.amount);
+
+        msg!("CPI: whirlpool decrease_liquidity instruction");
+        whirlpool_cpi::cpi::decrease_liquidity(
+            CpiContext::new_with_signer(
+                ctx.accounts.whirlpool_program.to_account_info(),
+                whirlpool_cpi::cpi::accounts::ModifyLiquidity {
+                    whirlpool: ctx.accounts.whirlpool.to_account_info(),
+                    token_program: ctx.accounts.token_program.to_account_info(),
+                    position_authority: ctx.accounts.vault.to_account_info(),
+                    position: ctx.accounts.position.as_ref()
+                        .ok_or(VaultError::MissingPosition)?.to_account_info(),
+                    position_token_account: ctx.accounts.position_token_account.as_ref()
+                        .ok_or(VaultError::MissingPosition)?.to_account_info(),
+                    token_owner_account_a: ctx.accounts.token_a_vault.to_account_info(),
+                    token_owner_account_b: ctx.accounts.token_b_vault.to_account_info(),
+                    token_vault_a: ctx.accounts.pool_token_vault_a.as_ref()
+                        .ok_or(VaultError::MissingPosition)?.to_account_info(),
+                    token_vault_b: ctx.accounts.pool_token_vault_b.as_ref()
+                        .ok_or(VaultError::MissingPosition)?.to_account_info(),
+                    tick_array_lower: ctx.accounts.tick_array_lower.as_ref()
+                        .ok_or(VaultError::MissingPosition)?.to_account_info(),
+                    tick_array_upper: ctx.accounts.tick_array_upper.as_ref()
+                        .ok_or(VaultError::MissingPosition)?.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            liquidity_amount,
+            0,
+            0,
+        )?;
+
+        ctx.accounts.token_a_vault.reload()?;
+        ctx.accounts.token_b_vault.reload()?;
+        token_a_amount = ctx.accounts.token_a_vault.amount
+            .checked_sub(balance_a_before)
+            .and_then(|amount| amount.checked_add(idle_a_amount))
+            .ok_or(VaultError::ArithmeticOverflow)?;
+        token_b_amount = ctx.accounts.token_b_vault.amount
+            .checked_sub(balance_b_before)
+            .and_then(|amount| amount.checked_add(idle_b_amount))
+            .ok_or(VaultError::ArithmeticOverflow)?;
     }
+    let vault = &ctx.accounts.vault;
+
+    // The exit fee is kept back from the redeemed amounts and sent to the treasury
+    let fee_a_amount = calculate_fee_amount(token_a_amount, vault.withdraw_fee_bps, Rounding::Up)?;
+    let fee_b_amount = calculate_fee_amount(token_b_amount, vault.withdraw_fee_bps, Rounding::Up)?;
+    let token_a_amount = token_a_amount - fee_a_amount;
+    let token_b_amount = token_b_amount - fee_b_amount;
 
     require!(
         token_a_amount >= token_min_a_amount && token_b_amount >= token_min_b_amount,
         VaultError::SlippageExceeded
     );
 
-    let burn_amount = calculate_proportional_amount(
-        share_amount,
-        token_a_amount,
-        total_token_a_amount,
-    )?;
+    if fee_a_amount > 0 {
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.token_a_vault.to_account_info(),
+                    to: ctx.accounts.treasury_token_a.to_account_info(),
+                    authority: ctx.accounts.vault.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            fee_a_amount,
+        )?;
+    }
+
+    if fee_b_amount > 0 {
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.token_b_vault.to_account_info(),
+                    to: ctx.accounts.treasury_token_b.to_account_info(),
+                    authority: ctx.accounts.vault.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            fee_b_amount,
+        )?;
+    }
 
     if token_a_amount > 0 {
         token::transfer(
@@ -124,7 +325,7 @@ pub fn withdraw(
                     to: ctx.accounts.user_token_a.to_account_info(),
                     authority: ctx.accounts.vault.to_account_info(),
                 },
-                &[&[b"vault".as_ref(), whirlpool.key().as_ref(), &[vault.bump]]],
+                signer_seeds,
             ),
             token_a_amount,
         )?;
@@ -139,24 +340,25 @@ pub fn withdraw(
                     to: ctx.accounts.user_token_b.to_account_info(),
                     authority: ctx.accounts.vault.to_account_info(),
                 },
-                &[&[b"vault".as_ref(), whirlpool.key().as_ref(), &[vault.bump]]],
+                signer_seeds,
             ),
             token_b_amount,
         )?;
     }
 
     // Burn shares
-    token::burn(
-        CpiContext::new(
-            ctx.accounts.token_program.to_account_info(),
-            token::Burn {
-                mint: ctx.accounts.share_mint.to_account_info(),
-                from: ctx.accounts.user_share.to_account_info(),
-                authority: ctx.accounts.user.to_account_info(),
-            },
-        ),
-        burn_amount,
+    burn_shares(
+        ctx.accounts.token_program.to_account_info(),
+        ctx.accounts.share_mint.to_account_info(),
+        ctx.accounts.user_share.to_account_info(),
+        ctx.accounts.user.to_account_info(),
+        ctx.accounts.vault.to_account_info(),
+        signer_seeds,
+        share_amount,
     )?;
 
+    ctx.accounts.user_share.reload()?;
+    ctx.accounts.reward_state.record_shares(&mut ctx.accounts.user_reward_checkpoint, ctx.accounts.user_share.amount)?;
+
     Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,341 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Token, TokenAccount, Mint, Transfer};
+use whirlpool_cpi::{self, program::Whirlpool as WhirlpoolProgram, state::{Position, TickArray, Whirlpool}};
+use crate::{
+    errors::VaultError,
+    state::{ObservationState, RewardState, UserRewardCheckpoint, Vault},
+    util::{
+        accrue_fees, burn_shares, calculate_fee_amount, calculate_proportional_amount, calculate_total_tokens,
+        calculate_vault_value, check_price_deviation, get_reference_price_x64, get_valuation_price, mint_shares,
+        sqrt_price_to_price_x64, PriceSource, Rounding, MAX_SQRT_PRICE, MIN_SQRT_PRICE,
+    },
+};
+
+#[derive(Accounts)]
+pub struct WithdrawSingle<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    pub whirlpool_program: Program<'info, WhirlpoolProgram>,
+
+    #[account(mut, constraint = whirlpool.key() == vault.whirlpool)]
+    pub whirlpool: Box<Account<'info, Whirlpool>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"vault".as_ref(),
+            whirlpool.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub vault: Box<Account<'info, Vault>>,
+
+    // Required whenever the vault has a position open
+    #[account(has_one = whirlpool, address = vault.position)]
+    pub position: Option<Box<Account<'info, Position>>>,
+
+    // Receives the one token the user picks, either token A or token B
+    #[account(
+        mut,
+        constraint = user_token.mint == vault.token_a_mint || user_token.mint == vault.token_b_mint,
+    )]
+    pub user_token: Box<Account<'info, TokenAccount>>,
+    #[account(mut, address = vault.token_a_vault)]
+    pub token_a_vault: Box<Account<'info, TokenAccount>>,
+    #[account(mut, address = vault.token_b_vault)]
+    pub token_b_vault: Box<Account<'info, TokenAccount>>,
+    #[account(mut, address = whirlpool.token_vault_a)]
+    pub pool_token_vault_a: Box<Account<'info, TokenAccount>>,
+    #[account(mut, address = whirlpool.token_vault_b)]
+    pub pool_token_vault_b: Box<Account<'info, TokenAccount>>,
+
+    // Tick arrays the swap walks through, starting at the current tick
+    #[account(mut, has_one = whirlpool)]
+    pub tick_array_0: AccountLoader<'info, TickArray>,
+    #[account(mut, has_one = whirlpool)]
+    pub tick_array_1: AccountLoader<'info, TickArray>,
+    #[account(mut, has_one = whirlpool)]
+    pub tick_array_2: AccountLoader<'info, TickArray>,
+    /// CHECK: checked by the whirlpool program
+    #[account(
+        seeds = [b"oracle".as_ref(), whirlpool.key().as_ref()],
+        bump,
+        seeds::program = whirlpool_program.key(),
+    )]
+    pub oracle: UncheckedAccount<'info>,
+
+    #[account(mut, address = vault.share_mint)]
+    pub share_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        constraint = user_share.mint == vault.share_mint,
+        constraint = user_share.owner == user.key(),
+    )]
+    pub user_share: Box<Account<'info, TokenAccount>>,
+    #[account(mut, address = vault.fee_recipient)]
+    pub fee_recipient: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(mut, address = vault.treasury_token_a)]
+    pub treasury_token_a: Box<Account<'info, TokenAccount>>,
+    #[account(mut, address = vault.treasury_token_b)]
+    pub treasury_token_b: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: price feed recorded on the vault, only needed in oracle pricing modes
+    #[account(address = vault.token_a_price_feed)]
+    pub token_a_price_feed: Option<UncheckedAccount<'info>>,
+    /// CHECK: price feed recorded on the vault, only needed in oracle pricing modes
+    #[account(address = vault.token_b_price_feed)]
+    pub token_b_price_feed: Option<UncheckedAccount<'info>>,
+
+    #[account(
+        seeds = [
+            b"observation".as_ref(),
+            whirlpool.key().as_ref(),
+        ],
+        bump,
+        has_one = vault,
+    )]
+    pub observation_state: Option<Box<Account<'info, ObservationState>>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"reward_state".as_ref(),
+            whirlpool.key().as_ref(),
+        ],
+        bump,
+        has_one = vault,
+    )]
+    pub reward_state: Box<Account<'info, RewardState>>,
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + UserRewardCheckpoint::LEN,
+        seeds = [
+            b"reward_checkpoint".as_ref(),
+            vault.key().as_ref(),
+            user.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub user_reward_checkpoint: Box<Account<'info, UserRewardCheckpoint>>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+// Redeems shares for their proportional amounts of both tokens, then swaps the
+// leg the user did not pick through the whirlpool
+pub fn withdraw_single(
+    ctx: Context<WithdrawSingle>,
+    share_amount: u64,
+    min_amount_out: u64,
+) -> Result<()> {
+    // Stream rewards to the current holders before the share supply changes
+    ctx.accounts.reward_state.update(Clock::get()?.unix_timestamp)?;
+    let user_reward_checkpoint = &mut ctx.accounts.user_reward_checkpoint;
+    user_reward_checkpoint.vault = ctx.accounts.vault.key();
+    user_reward_checkpoint.user = ctx.accounts.user.key();
+    user_reward_checkpoint.settle(&ctx.accounts.reward_state, ctx.accounts.user_share.amount)?;
+
+    // Valuing the vault without its position would misprice the redemption
+    require!(
+        ctx.accounts.position.is_some() || ctx.accounts.vault.position == Pubkey::default(),
+        VaultError::MissingPosition
+    );
+
+    let vault = &ctx.accounts.vault;
+    let total_shares = ctx.accounts.share_mint.supply;
+    let whirlpool = &ctx.accounts.whirlpool;
+    let is_token_a = ctx.accounts.user_token.mint == vault.token_a_mint;
+    // Get the tick index and sqrt_price the vault is valued at
+    let (current_tick_index, sqrt_price) = get_valuation_price(
+        vault,
+        whirlpool,
+        ctx.accounts.observation_state.as_deref(),
+    )?;
+
+    let oracle_prices = PriceSource::new(
+        vault.pricing_mode,
+        ctx.accounts.token_a_price_feed.as_ref(),
+        ctx.accounts.token_b_price_feed.as_ref(),
+    )?
+    .token_prices(vault)?;
+
+    // Refuse to redeem or swap against a pool pushed away from the reference price
+    check_price_deviation(
+        get_reference_price_x64(vault, sqrt_price, oracle_prices)?,
+        sqrt_price_to_price_x64(whirlpool.sqrt_price)?,
+        vault.max_price_deviation_bps,
+    )?;
+
+    // Calculate total value including tokens in Whirlpool position
+    let (total_token_a_amount, total_token_b_amount) = if let Some(position) = &ctx.accounts.position {
+        calculate_total_tokens(
+            ctx.accounts.token_a_vault.amount,
+            ctx.accounts.token_b_vault.amount,
+            position.liquidity,
+            current_tick_index,
+            sqrt_price,
+            vault.lower_tick,
+            vault.upper_tick,
+        )?
+    } else {
+        (ctx.accounts.token_a_vault.amount, ctx.accounts.token_b_vault.amount)
+    };
+
+    // Accrue management and performance fees before pricing the redemption
+    let total_value = calculate_vault_value(total_token_a_amount, total_token_b_amount, oracle_prices, sqrt_price)?;
+    let fee_shares = accrue_fees(
+        &mut ctx.accounts.vault,
+        total_value,
+        total_shares,
+        Clock::get()?.unix_timestamp,
+    )?;
+    let whirlpool_key = ctx.accounts.whirlpool.key();
+    let signer_seeds: &[&[&[u8]]] = &[&[b"vault".as_ref(), whirlpool_key.as_ref(), &[ctx.accounts.vault.bump]]];
+    if fee_shares > 0 {
+        let fee_recipient = ctx.accounts.fee_recipient.as_ref().ok_or(VaultError::MissingFeeRecipient)?;
+        mint_shares(
+            ctx.accounts.token_program.to_account_info(),
+            ctx.accounts.share_mint.to_account_info(),
+            fee_recipient.to_account_info(),
+            ctx.accounts.vault.to_account_info(),
+            signer_seeds,
+            fee_shares,
+        )?;
+    }
+    let total_shares = total_shares
+        .checked_add(fee_shares)
+        .ok_or(VaultError::ArithmeticOverflow)?;
+
+    let token_a_amount = calculate_proportional_amount(total_token_a_amount, share_amount, total_shares, Rounding::Down)?;
+    let token_b_amount = calculate_proportional_amount(total_token_b_amount, share_amount, total_shares, Rounding::Down)?;
+    require!(
+        token_a_amount <= ctx.accounts.token_a_vault.amount && token_b_amount <= ctx.accounts.token_b_vault.amount,
+        VaultError::InsufficientIdleBalance
+    );
+
+    // The exit fee is kept back from the redeemed amounts and sent to the treasury
+    let withdraw_fee_bps = ctx.accounts.vault.withdraw_fee_bps;
+    let fee_a_amount = calculate_fee_amount(token_a_amount, withdraw_fee_bps, Rounding::Up)?;
+    let fee_b_amount = calculate_fee_amount(token_b_amount, withdraw_fee_bps, Rounding::Up)?;
+    let token_a_amount = token_a_amount - fee_a_amount;
+    let token_b_amount = token_b_amount - fee_b_amount;
+
+    if fee_a_amount > 0 {
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.token_a_vault.to_account_info(),
+                    to: ctx.accounts.treasury_token_a.to_account_info(),
+                    authority: ctx.accounts.vault.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            fee_a_amount,
+        )?;
+    }
+
+    if fee_b_amount > 0 {
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.token_b_vault.to_account_info(),
+                    to: ctx.accounts.treasury_token_b.to_account_info(),
+                    authority: ctx.accounts.vault.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            fee_b_amount,
+        )?;
+    }
+
+    // Swap the other leg into the token the user picked
+    let (amount_out, swap_amount_in) = if is_token_a {
+        (token_a_amount, token_b_amount)
+    } else {
+        (token_b_amount, token_a_amount)
+    };
+    let a_to_b = !is_token_a;
+    let mut swap_amount_out = 0;
+    if swap_amount_in > 0 {
+        // The fee transfers above moved tokens out, read the balance the swap starts from
+        ctx.accounts.token_a_vault.reload()?;
+        ctx.accounts.token_b_vault.reload()?;
+        let balance_before = if is_token_a { ctx.accounts.token_a_vault.amount } else { ctx.accounts.token_b_vault.amount };
+
+        msg!("CPI: whirlpool swap instruction");
+        whirlpool_cpi::cpi::swap(
+            CpiContext::new_with_signer(
+                ctx.accounts.whirlpool_program.to_account_info(),
+                whirlpool_cpi::cpi::accounts::Swap {
+                    token_program: ctx.accounts.token_program.to_account_info(),
+                    token_authority: ctx.accounts.vault.to_account_info(),
+                    whirlpool: ctx.accounts.whirlpool.to_account_info(),
+                    token_owner_account_a: ctx.accounts.token_a_vault.to_account_info(),
+                    token_vault_a: ctx.accounts.pool_token_vault_a.to_account_info(),
+                    token_owner_account_b: ctx.accounts.token_b_vault.to_account_info(),
+                    token_vault_b: ctx.accounts.pool_token_vault_b.to_account_info(),
+                    tick_array_0: ctx.accounts.tick_array_0.to_account_info(),
+                    tick_array_1: ctx.accounts.tick_array_1.to_account_info(),
+                    tick_array_2: ctx.accounts.tick_array_2.to_account_info(),
+                    oracle: ctx.accounts.oracle.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            swap_amount_in,
+            0,
+            if a_to_b { MIN_SQRT_PRICE } else { MAX_SQRT_PRICE },
+            true,
+            a_to_b,
+        )?;
+
+        ctx.accounts.token_a_vault.reload()?;
+        ctx.accounts.token_b_vault.reload()?;
+        let balance_after = if is_token_a { ctx.accounts.token_a_vault.amount } else { ctx.accounts.token_b_vault.amount };
+        swap_amount_out = balance_after
+            .checked_sub(balance_before)
+            .ok_or(VaultError::ArithmeticOverflow)?;
+    }
+
+    let amount_out = amount_out
+        .checked_add(swap_amount_out)
+        .ok_or(VaultError::ArithmeticOverflow)?;
+    require!(amount_out >= min_amount_out, VaultError::SlippageExceeded);
+
+    token::transfer(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: if is_token_a {
+                    ctx.accounts.token_a_vault.to_account_info()
+                } else {
+                    ctx.accounts.token_b_vault.to_account_info()
+                },
+                to: ctx.accounts.user_token.to_account_info(),
+                authority: ctx.accounts.vault.to_account_info(),
+            },
+            signer_seeds,
+        ),
+        amount_out,
+    )?;
+
+    // Burn shares
+    burn_shares(
+        ctx.accounts.token_program.to_account_info(),
+        ctx.accounts.share_mint.to_account_info(),
+        ctx.accounts.user_share.to_account_info(),
+        ctx.accounts.user.to_account_info(),
+        ctx.accounts.vault.to_account_info(),
+        signer_seeds,
+        share_amount,
+    )?;
+
+    ctx.accounts.user_share.reload()?;
+    ctx.accounts.reward_state.record_shares(&mut ctx.accounts.user_reward_checkpoint, ctx.accounts.user_share.amount)?;
+
+    Ok(())
+}
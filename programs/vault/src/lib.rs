@@ -3,8 +3,10 @@ pub mod instructions;
 pub mod state;
 pub mod util;
 pub mod errors;
+pub mod events;
 
 use instructions::*;
+use state::*;
 use util::*;
 
 declare_id!("3A6RUfKQe3NDKYX9aFyBS21juUnbtkZi3djaoHmgXTef");
@@ -15,20 +17,24 @@ pub mod vault {
 
     pub fn initialize_vault(
         ctx: Context<InitializeVault>,
-        token_a_price: u64,
-        token_b_price: u64,
         lower_tick: i32,
         upper_tick: i32,
+        pricing_mode: PricingMode,
+        max_price_age: u64,
+        max_confidence_bps: u16,
+        max_tick_deviation: u32,
+        twap_window: u64,
     ) -> Result<()> {
-        instructions::initialize_vault(ctx, token_a_price, token_b_price, lower_tick, upper_tick)
-    }
-
-    pub fn update_prices(
-        ctx: Context<UpdatePrices>,
-        token_a_price: u64,
-        token_b_price: u64,
-    ) -> Result<()> {
-        instructions::update_prices(ctx, token_a_price, token_b_price)
+        instructions::initialize_vault(
+            ctx,
+            lower_tick,
+            upper_tick,
+            pricing_mode,
+            max_price_age,
+            max_confidence_bps,
+            max_tick_deviation,
+            twap_window,
+        )
     }
 
     pub fn update_ticks(
@@ -39,6 +45,129 @@ pub mod vault {
         instructions::update_ticks(ctx, lower_tick, upper_tick)
     }
 
+    pub fn update_price_config(
+        ctx: Context<UpdatePriceConfig>,
+        max_price_age: u64,
+        max_confidence_bps: u16,
+        max_tick_deviation: u32,
+        max_price_deviation_bps: u16,
+    ) -> Result<()> {
+        instructions::update_price_config(
+            ctx,
+            max_price_age,
+            max_confidence_bps,
+            max_tick_deviation,
+            max_price_deviation_bps,
+        )
+    }
+
+    pub fn update_fee_config(
+        ctx: Context<UpdateFeeConfig>,
+        management_fee_bps: u16,
+        performance_fee_bps: u16,
+    ) -> Result<()> {
+        instructions::update_fee_config(ctx, management_fee_bps, performance_fee_bps)
+    }
+
+    pub fn update_fee_schedule(
+        ctx: Context<UpdateFeeSchedule>,
+        deposit_fee_bps: u16,
+        withdraw_fee_bps: u16,
+    ) -> Result<()> {
+        instructions::update_fee_schedule(ctx, deposit_fee_bps, withdraw_fee_bps)
+    }
+
+    pub fn update_deposit_mode(
+        ctx: Context<UpdateDepositMode>,
+        deposit_mode: DepositMode,
+    ) -> Result<()> {
+        instructions::update_deposit_mode(ctx, deposit_mode)
+    }
+
+    pub fn update_keeper_config(
+        ctx: Context<UpdateKeeperConfig>,
+        compound_bounty_bps: u16,
+        min_compound_interval: u64,
+    ) -> Result<()> {
+        instructions::update_keeper_config(ctx, compound_bounty_bps, min_compound_interval)
+    }
+
+    pub fn update_rebalance_policy(
+        ctx: Context<UpdateRebalancePolicy>,
+        max_position_width: u32,
+        target_position_width: u32,
+        rebalance_threshold_ticks: u32,
+        min_rebalance_interval: u64,
+    ) -> Result<()> {
+        instructions::update_rebalance_policy(
+            ctx,
+            max_position_width,
+            target_position_width,
+            rebalance_threshold_ticks,
+            min_rebalance_interval,
+        )
+    }
+
+    pub fn update_roles(
+        ctx: Context<UpdateRoles>,
+        operator: Pubkey,
+        fee_manager: Pubkey,
+        pauser: Pubkey,
+    ) -> Result<()> {
+        instructions::update_roles(ctx, operator, fee_manager, pauser)
+    }
+
+    pub fn set_paused(
+        ctx: Context<SetPaused>,
+        paused: bool,
+    ) -> Result<()> {
+        instructions::set_paused(ctx, paused)
+    }
+
+    pub fn propose_admin(
+        ctx: Context<ProposeAdmin>,
+        new_admin: Pubkey,
+    ) -> Result<()> {
+        instructions::propose_admin(ctx, new_admin)
+    }
+
+    pub fn accept_admin(
+        ctx: Context<AcceptAdmin>,
+    ) -> Result<()> {
+        instructions::accept_admin(ctx)
+    }
+
+    pub fn schedule_change(
+        ctx: Context<ScheduleChange>,
+        change: ParameterChange,
+    ) -> Result<()> {
+        instructions::schedule_change(ctx, change)
+    }
+
+    pub fn execute_change(
+        ctx: Context<ExecuteChange>,
+    ) -> Result<()> {
+        instructions::execute_change(ctx)
+    }
+
+    pub fn cancel_change(
+        ctx: Context<CancelChange>,
+    ) -> Result<()> {
+        instructions::cancel_change(ctx)
+    }
+
+    pub fn update_reference_tick(
+        ctx: Context<UpdateReferenceTick>,
+    ) -> Result<()> {
+        instructions::update_reference_tick(ctx)
+    }
+
+    pub fn record_observation(
+        ctx: Context<RecordObservation>,
+    ) -> Result<()> {
+        instructions::record_observation(ctx)
+    }
+
     pub fn open_position(
         ctx: Context<OpenPosition>,
     ) -> Result<()> {
@@ -66,6 +195,32 @@ pub mod vault {
         instructions::collect_fees(ctx)
     }
 
+    pub fn collect_rewards<'info>(
+        ctx: Context<'_, '_, '_, 'info, CollectRewards<'info>>,
+    ) -> Result<()> {
+        instructions::collect_rewards(ctx)
+    }
+
+    pub fn compound(
+        ctx: Context<Compound>,
+    ) -> Result<()> {
+        instructions::compound(ctx)
+    }
+
+    pub fn rebalance<'info>(
+        ctx: Context<'_, '_, '_, 'info, Rebalance<'info>>,
+    ) -> Result<()> {
+        instructions::rebalance(ctx)
+    }
+
+    pub fn swap_to_ratio(
+        ctx: Context<SwapToRatio>,
+        max_slippage_bps: u16,
+        sqrt_price_limit: u128,
+    ) -> Result<()> {
+        instructions::swap_to_ratio(ctx, max_slippage_bps, sqrt_price_limit)
+    }
+
     pub fn close_position(
         ctx: Context<ClosePosition>,
     ) -> Result<()> {
@@ -76,8 +231,25 @@ pub mod vault {
         ctx: Context<Deposit>,
         amount_a: u64,
         amount_b: u64,
+        min_shares_out: u64,
+        deadline: Option<i64>,
     ) -> Result<()> {
-        instructions::deposit(ctx, amount_a, amount_b)
+        instructions::deposit(ctx, amount_a, amount_b, min_shares_out, deadline)
+    }
+
+    pub fn deposit_single(
+        ctx: Context<DepositSingle>,
+        amount: u64,
+        min_shares_out: u64,
+        max_slippage_bps: u16,
+    ) -> Result<()> {
+        instructions::deposit_single(ctx, amount, min_shares_out, max_slippage_bps)
+    }
+
+    pub fn claim_rewards<'info>(
+        ctx: Context<'_, '_, '_, 'info, ClaimRewards<'info>>,
+    ) -> Result<()> {
+        instructions::claim_rewards(ctx)
     }
 
     pub fn withdraw(
@@ -88,4 +260,12 @@ pub mod vault {
     ) -> Result<()> {
         instructions::withdraw(ctx, share_amount, token_min_a_amount, token_min_b_amount)
     }
+
+    pub fn withdraw_single(
+        ctx: Context<WithdrawSingle>,
+        share_amount: u64,
+        min_amount_out: u64,
+    ) -> Result<()> {
+        instructions::withdraw_single(ctx, share_amount, min_amount_out)
+    }
 }
\ No newline at end of file
@@ -1,2 +1,11 @@
 pub mod vault;
-pub use vault::*;
\ No newline at end of file
+pub use vault::*;
+
+pub mod observation;
+pub use observation::*;
+
+pub mod reward;
+pub use reward::*;
+
+pub mod pending_change;
+pub use pending_change::*;
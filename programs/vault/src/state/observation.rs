@@ -0,0 +1,79 @@
+use anchor_lang::prelude::*;
+
+use crate::errors::VaultError;
+
+pub const OBSERVATION_BUFFER_LEN: usize = 32;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct Observation {
+    pub slot: u64,
+    // Sum of tick * slots since the first observation. The tick recorded at an
+    // observation is the one in force until the next observation.
+    pub tick_cumulative: i128,
+    pub tick_current_index: i32,
+}
+
+impl Observation {
+    pub const LEN: usize = 8 + 16 + 4;
+
+    // Cumulative extended from this observation up to a later slot
+    pub fn tick_cumulative_at(&self, slot: u64) -> Result<i128> {
+        let elapsed = slot.checked_sub(self.slot).ok_or(VaultError::InsufficientObservations)?;
+        Ok(self
+            .tick_cumulative
+            .checked_add(self.tick_current_index as i128 * elapsed as i128)
+            .ok_or(VaultError::ArithmeticOverflow)?)
+    }
+}
+
+#[account]
+pub struct ObservationState {
+    pub vault: Pubkey,
+    pub whirlpool: Pubkey,
+    // Next slot of the ring buffer to write
+    pub head: u16,
+    pub count: u16,
+    pub observations: [Observation; OBSERVATION_BUFFER_LEN],
+}
+
+impl ObservationState {
+    pub const LEN: usize = 32 + 32 + 2 + 2 + Observation::LEN * OBSERVATION_BUFFER_LEN;
+
+    // Fewest slots between two records, so that a full buffer always spans the
+    // TWAP window and cannot be flushed by recording in quick succession
+    pub fn min_interval(twap_window: u64) -> u64 {
+        twap_window.div_ceil(OBSERVATION_BUFFER_LEN as u64 - 1).max(1)
+    }
+
+    pub fn latest(&self) -> Option<&Observation> {
+        if self.count == 0 {
+            return None;
+        }
+        let index = (self.head as usize + OBSERVATION_BUFFER_LEN - 1) % OBSERVATION_BUFFER_LEN;
+        Some(&self.observations[index])
+    }
+
+    pub fn record(&mut self, slot: u64, tick_current_index: i32) -> Result<()> {
+        let tick_cumulative = match self.latest() {
+            Some(latest) => latest.tick_cumulative_at(slot)?,
+            None => 0,
+        };
+        self.observations[self.head as usize] = Observation {
+            slot,
+            tick_cumulative,
+            tick_current_index,
+        };
+        self.head = ((self.head as usize + 1) % OBSERVATION_BUFFER_LEN) as u16;
+        self.count = (self.count as usize + 1).min(OBSERVATION_BUFFER_LEN) as u16;
+        Ok(())
+    }
+
+    // Observations ordered from oldest to newest
+    pub fn ordered(&self) -> Vec<Observation> {
+        let count = self.count as usize;
+        let start = (self.head as usize + OBSERVATION_BUFFER_LEN - count) % OBSERVATION_BUFFER_LEN;
+        (0..count)
+            .map(|i| self.observations[(start + i) % OBSERVATION_BUFFER_LEN])
+            .collect()
+    }
+}
@@ -0,0 +1,173 @@
+use anchor_lang::prelude::*;
+
+use crate::{
+    errors::VaultError,
+    state::{
+        DepositMode, Vault, MAX_COMPOUND_BOUNTY_BPS, MAX_DEPOSIT_FEE_BPS, MAX_MANAGEMENT_FEE_BPS,
+        MAX_PERFORMANCE_FEE_BPS, MAX_WITHDRAW_FEE_BPS,
+    },
+    util::validate_position_ticks,
+};
+
+// Longest delay the admin can put on parameter changes
+pub const MAX_TIMELOCK_DELAY: i64 = 30 * 24 * 60 * 60;
+
+// Admin change to the vault's economics that can be timelocked
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ParameterChange {
+    Ticks {
+        lower_tick: i32,
+        upper_tick: i32,
+    },
+    PriceConfig {
+        max_price_age: u64,
+        max_confidence_bps: u16,
+        max_tick_deviation: u32,
+        max_price_deviation_bps: u16,
+    },
+    FeeConfig {
+        fee_recipient: Pubkey,
+        management_fee_bps: u16,
+        performance_fee_bps: u16,
+    },
+    FeeSchedule {
+        deposit_fee_bps: u16,
+        withdraw_fee_bps: u16,
+    },
+    TimelockDelay {
+        timelock_delay: i64,
+    },
+    KeeperConfig {
+        compound_bounty_bps: u16,
+        min_compound_interval: u64,
+    },
+    DepositMode {
+        deposit_mode: DepositMode,
+    },
+    RebalancePolicy {
+        max_position_width: u32,
+        target_position_width: u32,
+        rebalance_threshold_ticks: u32,
+        min_rebalance_interval: u64,
+    },
+    Roles {
+        operator: Pubkey,
+        fee_manager: Pubkey,
+        pauser: Pubkey,
+    },
+}
+
+impl ParameterChange {
+    // Discriminant plus the largest variant, Roles
+    pub const LEN: usize = 1 + 32 * 3;
+
+    pub fn validate(&self, vault: &Vault, tick_spacing: u16) -> Result<()> {
+        match *self {
+            ParameterChange::Ticks { lower_tick, upper_tick } => {
+                validate_position_ticks(lower_tick, upper_tick, tick_spacing, vault.max_position_width)?;
+            }
+            ParameterChange::FeeConfig { management_fee_bps, performance_fee_bps, .. } => {
+                require!(management_fee_bps <= MAX_MANAGEMENT_FEE_BPS, VaultError::FeeTooHigh);
+                require!(performance_fee_bps <= MAX_PERFORMANCE_FEE_BPS, VaultError::FeeTooHigh);
+            }
+            ParameterChange::FeeSchedule { deposit_fee_bps, withdraw_fee_bps } => {
+                require!(deposit_fee_bps <= MAX_DEPOSIT_FEE_BPS, VaultError::FeeTooHigh);
+                require!(withdraw_fee_bps <= MAX_WITHDRAW_FEE_BPS, VaultError::FeeTooHigh);
+            }
+            ParameterChange::TimelockDelay { timelock_delay } => {
+                require!(
+                    (0..=MAX_TIMELOCK_DELAY).contains(&timelock_delay),
+                    VaultError::InvalidTimelockDelay
+                );
+            }
+            ParameterChange::KeeperConfig { compound_bounty_bps, .. } => {
+                require!(compound_bounty_bps <= MAX_COMPOUND_BOUNTY_BPS, VaultError::FeeTooHigh);
+            }
+            ParameterChange::RebalancePolicy { max_position_width, target_position_width, .. } => {
+                require!(
+                    target_position_width > 0 && target_position_width <= max_position_width,
+                    VaultError::InvalidRebalancePolicy
+                );
+            }
+            ParameterChange::PriceConfig { .. }
+            | ParameterChange::DepositMode { .. }
+            | ParameterChange::Roles { .. } => {}
+        }
+        Ok(())
+    }
+
+    pub fn apply(&self, vault: &mut Vault, tick_spacing: u16, now: i64) -> Result<()> {
+        self.validate(vault, tick_spacing)?;
+
+        match *self {
+            ParameterChange::Ticks { lower_tick, upper_tick } => {
+                vault.lower_tick = lower_tick;
+                vault.upper_tick = upper_tick;
+            }
+            ParameterChange::PriceConfig {
+                max_price_age,
+                max_confidence_bps,
+                max_tick_deviation,
+                max_price_deviation_bps,
+            } => {
+                vault.max_price_age = max_price_age;
+                vault.max_confidence_bps = max_confidence_bps;
+                vault.max_tick_deviation = max_tick_deviation;
+                vault.max_price_deviation_bps = max_price_deviation_bps;
+            }
+            ParameterChange::FeeConfig { fee_recipient, management_fee_bps, performance_fee_bps } => {
+                vault.fee_recipient = fee_recipient;
+                vault.management_fee_bps = management_fee_bps;
+                vault.performance_fee_bps = performance_fee_bps;
+                // New rates only apply from now on, management fees are not charged retroactively
+                vault.last_fee_accrual = now;
+            }
+            ParameterChange::FeeSchedule { deposit_fee_bps, withdraw_fee_bps } => {
+                vault.deposit_fee_bps = deposit_fee_bps;
+                vault.withdraw_fee_bps = withdraw_fee_bps;
+            }
+            ParameterChange::TimelockDelay { timelock_delay } => {
+                vault.timelock_delay = timelock_delay;
+            }
+            ParameterChange::KeeperConfig { compound_bounty_bps, min_compound_interval } => {
+                vault.compound_bounty_bps = compound_bounty_bps;
+                vault.min_compound_interval = min_compound_interval;
+            }
+            ParameterChange::DepositMode { deposit_mode } => {
+                vault.deposit_mode = deposit_mode;
+            }
+            ParameterChange::RebalancePolicy {
+                max_position_width,
+                target_position_width,
+                rebalance_threshold_ticks,
+                min_rebalance_interval,
+            } => {
+                vault.max_position_width = max_position_width;
+                vault.target_position_width = target_position_width;
+                vault.rebalance_threshold_ticks = rebalance_threshold_ticks;
+                vault.min_rebalance_interval = min_rebalance_interval;
+            }
+            ParameterChange::Roles { operator, fee_manager, pauser } => {
+                vault.operator = operator;
+                vault.fee_manager = fee_manager;
+                vault.pauser = pauser;
+            }
+        }
+        Ok(())
+    }
+}
+
+// A scheduled change, executable once the vault's timelock delay has passed
+// since it was scheduled, so share holders have time to exit first
+#[account]
+pub struct PendingChange {
+    pub vault: Pubkey,
+    pub id: u64,
+    pub change: ParameterChange,
+    pub scheduled_at: i64,
+    pub executable_at: i64,
+}
+
+impl PendingChange {
+    pub const LEN: usize = 32 + 8 + ParameterChange::LEN + 8 + 8;
+}
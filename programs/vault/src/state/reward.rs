@@ -0,0 +1,132 @@
+use anchor_lang::prelude::*;
+
+use crate::errors::VaultError;
+
+// Whirlpools pay out at most three reward tokens
+pub const NUM_REWARDS: usize = 3;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct RewardLedgerEntry {
+    pub mint: Pubkey,
+    // Vault-owned token account the reward is collected into
+    pub token_account: Pubkey,
+    pub total_collected: u64,
+    // Collected but not yet distributed or swapped
+    pub pending: u64,
+}
+
+impl RewardLedgerEntry {
+    pub const LEN: usize = 32 + 32 + 8 + 8;
+}
+
+#[account]
+pub struct RewardLedger {
+    pub vault: Pubkey,
+    // Indexed like the whirlpool's reward_infos
+    pub rewards: [RewardLedgerEntry; NUM_REWARDS],
+}
+
+impl RewardLedger {
+    pub const LEN: usize = 32 + RewardLedgerEntry::LEN * NUM_REWARDS;
+}
+
+// Fixed-point scale of the accumulated reward per share
+pub const REWARD_PER_SHARE_PRECISION: u128 = 1_000_000_000_000;
+// Collected rewards are streamed to share holders over this period, so a
+// deposit right before a collection does not capture rewards earned earlier
+pub const REWARD_DISTRIBUTION_PERIOD: i64 = 7 * 24 * 60 * 60;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct RewardAccumulator {
+    pub mint: Pubkey,
+    pub token_account: Pubkey,
+    pub reward_per_share: u128,
+    // Handed over by collect_rewards, not yet streamed to share holders
+    pub undistributed: u64,
+}
+
+impl RewardAccumulator {
+    pub const LEN: usize = 32 + 32 + 16 + 8;
+}
+
+#[account]
+pub struct RewardState {
+    pub vault: Pubkey,
+    pub last_update: i64,
+    // Sum of the shares recorded on user checkpoints, the supply rewards stream
+    // over. Dead shares, and fee shares until their holder checkpoints, are left
+    // out, so no part of a reward is stranded on shares that can never claim it.
+    pub checkpointed_shares: u64,
+    pub rewards: [RewardAccumulator; NUM_REWARDS],
+}
+
+impl RewardState {
+    pub const LEN: usize = 32 + 8 + 8 + RewardAccumulator::LEN * NUM_REWARDS;
+
+    // Stream undistributed rewards to the checkpointed shares since the last
+    // update. While there are none, the rewards keep waiting.
+    pub fn update(&mut self, now: i64) -> Result<()> {
+        let elapsed = now.saturating_sub(self.last_update).clamp(0, REWARD_DISTRIBUTION_PERIOD);
+        self.last_update = now;
+        let total_shares = self.checkpointed_shares;
+        if total_shares == 0 || elapsed == 0 {
+            return Ok(());
+        }
+
+        for reward in self.rewards.iter_mut() {
+            let released = (reward.undistributed as u128 * elapsed as u128 / REWARD_DISTRIBUTION_PERIOD as u128) as u64;
+            if released == 0 {
+                continue;
+            }
+            reward.reward_per_share = reward
+                .reward_per_share
+                .checked_add(released as u128 * REWARD_PER_SHARE_PRECISION / total_shares as u128)
+                .ok_or(VaultError::ArithmeticOverflow)?;
+            reward.undistributed -= released;
+        }
+        Ok(())
+    }
+
+    // Record a user's share balance on their checkpoint, keeping the streamed supply in step
+    pub fn record_shares(&mut self, checkpoint: &mut UserRewardCheckpoint, shares: u64) -> Result<()> {
+        self.checkpointed_shares = self
+            .checkpointed_shares
+            .checked_sub(checkpoint.shares)
+            .and_then(|total| total.checked_add(shares))
+            .ok_or(VaultError::ArithmeticOverflow)?;
+        checkpoint.shares = shares;
+        Ok(())
+    }
+}
+
+#[account]
+pub struct UserRewardCheckpoint {
+    pub vault: Pubkey,
+    pub user: Pubkey,
+    // Share balance recorded at the user's last deposit, withdraw or claim
+    pub shares: u64,
+    pub reward_per_share_paid: [u128; NUM_REWARDS],
+    pub owed: [u64; NUM_REWARDS],
+}
+
+impl UserRewardCheckpoint {
+    pub const LEN: usize = 32 + 32 + 8 + 16 * NUM_REWARDS + 8 * NUM_REWARDS;
+
+    // Credit rewards earned since the last checkpoint. Share accounts stay frozen
+    // between the vault's own mints and burns, so the recorded shares cannot be
+    // moved to another holder and counted on their checkpoint as well.
+    pub fn settle(&mut self, reward_state: &RewardState, current_shares: u64) -> Result<()> {
+        let eligible_shares = self.shares.min(current_shares) as u128;
+        for (i, reward) in reward_state.rewards.iter().enumerate() {
+            let earned = eligible_shares
+                .checked_mul(reward.reward_per_share - self.reward_per_share_paid[i])
+                .ok_or(VaultError::ArithmeticOverflow)?
+                / REWARD_PER_SHARE_PRECISION;
+            self.owed[i] = self.owed[i]
+                .checked_add(u64::try_from(earned).map_err(|_| VaultError::ArithmeticOverflow)?)
+                .ok_or(VaultError::ArithmeticOverflow)?;
+            self.reward_per_share_paid[i] = reward.reward_per_share;
+        }
+        Ok(())
+    }
+}
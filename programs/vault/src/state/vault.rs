@@ -1,23 +1,98 @@
 use anchor_lang::prelude::*;
 
+pub const SHARE_DECIMALS: u8 = 6;
+pub const DEFAULT_MAX_PRICE_DEVIATION_BPS: u16 = 100;
+pub const MAX_MANAGEMENT_FEE_BPS: u16 = 500;
+pub const MAX_PERFORMANCE_FEE_BPS: u16 = 3_000;
+pub const MAX_DEPOSIT_FEE_BPS: u16 = 100;
+pub const MAX_WITHDRAW_FEE_BPS: u16 = 100;
+pub const MAX_COMPOUND_BOUNTY_BPS: u16 = 1_000;
+// Shares the first deposit locks in the vault so the supply never returns to zero
+pub const DEAD_SHARES: u64 = 1_000;
+// Widest position rebalance may open, in tick spacings
+pub const DEFAULT_MAX_POSITION_WIDTH: u32 = 200;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PricingMode {
+    // USD prices read from the Pyth feeds recorded on the vault
+    Pyth,
+    // USD prices read from the Switchboard On-Demand pull feeds recorded on the vault
+    Switchboard,
+    // Token A valued in token B at the pool's own sqrt_price
+    Whirlpool,
+    // Token A valued in token B at the time-weighted average tick of the observation buffer
+    Twap,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DepositMode {
+    // Any token amounts, shares minted on their value at the vault's prices
+    Valued,
+    // Only the ratio the vault already holds is taken, the excess stays with the
+    // user and shares are minted pro rata on the limiting token
+    Proportional,
+}
+
 #[account]
 pub struct Vault {
+    // Owner: pricing, fee and policy parameters, and the other roles
     pub admin: Pubkey,
+    // Proposed new owner, who has to accept before ownership moves
+    pub pending_admin: Pubkey,
+    // Manages the position: open, add and remove liquidity, collect fees, close
+    pub operator: Pubkey,
+    // Harvests Whirlpool liquidity-mining rewards for share holders
+    pub fee_manager: Pubkey,
+    // Can pause deposits and keeper actions, withdrawals stay open
+    pub pauser: Pubkey,
+    pub paused: bool,
     pub whirlpool: Pubkey,
     pub token_a_mint: Pubkey,
     pub token_b_mint: Pubkey,
     pub token_a_vault: Pubkey,
     pub token_b_vault: Pubkey,
     pub share_mint: Pubkey,
-    pub token_a_price: u64,
-    pub token_b_price: u64,
+    pub pricing_mode: PricingMode,
+    pub deposit_mode: DepositMode,
+    pub token_a_price_feed: Pubkey,
+    pub token_b_price_feed: Pubkey,
+    pub max_price_age: u64,
+    pub max_confidence_bps: u16,
+    pub reference_tick: i32,
+    pub max_tick_deviation: u32,
+    pub twap_window: u64,
+    pub max_price_deviation_bps: u16,
+    pub fee_recipient: Pubkey,
+    pub management_fee_bps: u16,
+    pub performance_fee_bps: u16,
+    pub high_water_mark: u128,
+    pub last_fee_accrual: i64,
+    pub deposit_fee_bps: u16,
+    pub withdraw_fee_bps: u16,
+    pub treasury_token_a: Pubkey,
+    pub treasury_token_b: Pubkey,
+    pub compound_bounty_bps: u16,
+    pub min_compound_interval: u64,
+    pub last_compound_slot: u64,
+    pub max_position_width: u32,
+    pub target_position_width: u32,
+    pub rebalance_threshold_ticks: u32,
+    pub min_rebalance_interval: u64,
+    pub last_rebalance: i64,
+    // Seconds a scheduled parameter change waits before it can be executed,
+    // direct updates are only allowed while it is zero
+    pub timelock_delay: i64,
+    // Id of the next scheduled change, part of its PendingChange address
+    pub next_change_id: u64,
     pub token_a_decimal: u8,
     pub token_b_decimal: u8,
     pub lower_tick: i32,
     pub upper_tick: i32,
+    // Whirlpool position holding the vault's liquidity, default while none is open
+    pub position: Pubkey,
     pub bump: u8,
 }
 
 impl Vault {
-    pub const LEN: usize = 32 + 32 + 32 + 32 + 32 + 32 + 32 + 8 + 8 + 1 + 1 + 4 + 4 + 1;
-}
\ No newline at end of file
+    pub const LEN: usize = 32 + 32 + 32 + 32 + 32 + 1 + 32 + 32 + 32 + 32 + 32 + 32 + 1 + 1 + 32 + 32 + 8 + 2 + 4 + 4 + 8 + 2 + 32 + 2 + 2 + 16 + 8 + 2 + 2 + 32 + 32 + 2 + 8 + 8 + 4 + 4 + 4 + 8 + 8 + 8 + 8 + 1 + 1 + 4 + 4 + 32 + 1;
+}
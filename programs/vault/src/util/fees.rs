@@ -0,0 +1,259 @@
+use ethnum::U256;
+
+use crate::{errors::VaultError, state::Vault, util::{calculate_proportional_amount, Rounding}};
+
+pub const BPS_DENOMINATOR: u64 = 10_000;
+pub const SECONDS_PER_YEAR: u64 = 365 * 24 * 60 * 60;
+// Fixed-point scale of the value per share tracked by the high-water mark
+pub const VALUE_PER_SHARE_PRECISION: u128 = 1_000_000_000_000;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct FeeAccrual {
+    pub management_fee_shares: u64,
+    pub performance_fee_shares: u64,
+    pub high_water_mark: u128,
+}
+
+impl FeeAccrual {
+    pub fn total_shares(&self) -> Result<u64, VaultError> {
+        self.management_fee_shares
+            .checked_add(self.performance_fee_shares)
+            .ok_or(VaultError::ArithmeticOverflow)
+    }
+}
+
+fn to_u64(value: U256) -> Result<u64, VaultError> {
+    u64::try_from(value).map_err(|_| VaultError::ArithmeticOverflow)
+}
+
+// Shares to mint so the recipient owns management_fee_bps (annualised) of the
+// supply for the elapsed time: supply * f / (1 - f)
+pub fn calculate_management_fee_shares(
+    total_shares: u64,
+    management_fee_bps: u16,
+    elapsed: u64,
+) -> Result<u64, VaultError> {
+    if total_shares == 0 || management_fee_bps == 0 || elapsed == 0 {
+        return Ok(0);
+    }
+
+    let fee_numerator = U256::from(management_fee_bps) * U256::from(elapsed);
+    let fee_denominator = U256::from(BPS_DENOMINATOR) * U256::from(SECONDS_PER_YEAR);
+    if fee_numerator >= fee_denominator {
+        return Err(VaultError::CalculationFailure);
+    }
+
+    to_u64(U256::from(total_shares) * fee_numerator / (fee_denominator - fee_numerator))
+}
+
+pub fn calculate_value_per_share(total_value: u128, total_shares: u64) -> Result<u128, VaultError> {
+    if total_shares == 0 {
+        return Err(VaultError::CalculationFailure);
+    }
+
+    let value_per_share = U256::from(total_value) * U256::from(VALUE_PER_SHARE_PRECISION) / U256::from(total_shares);
+    u128::try_from(value_per_share).map_err(|_| VaultError::ArithmeticOverflow)
+}
+
+// Shares to mint so the recipient receives performance_fee_bps of the value
+// gained per share above the high-water mark
+pub fn calculate_performance_fee_shares(
+    total_value: u128,
+    total_shares: u64,
+    high_water_mark: u128,
+    performance_fee_bps: u16,
+) -> Result<u64, VaultError> {
+    if total_shares == 0 || performance_fee_bps == 0 || high_water_mark == 0 {
+        return Ok(0);
+    }
+
+    let value_per_share = calculate_value_per_share(total_value, total_shares)?;
+    if value_per_share <= high_water_mark {
+        return Ok(0);
+    }
+
+    let profit = U256::from(value_per_share - high_water_mark) * U256::from(total_shares)
+        / U256::from(VALUE_PER_SHARE_PRECISION);
+    let fee_value = profit * U256::from(performance_fee_bps) / U256::from(BPS_DENOMINATOR);
+    let remaining_value = U256::from(total_value) - fee_value;
+    if remaining_value == U256::ZERO {
+        return Err(VaultError::CalculationFailure);
+    }
+
+    to_u64(fee_value * U256::from(total_shares) / remaining_value)
+}
+
+// Management fee first, then the performance fee on the diluted supply. A zero
+// high-water mark is initialised to the current value per share without a fee.
+pub fn calculate_fee_accrual(
+    total_value: u128,
+    total_shares: u64,
+    high_water_mark: u128,
+    management_fee_bps: u16,
+    performance_fee_bps: u16,
+    elapsed: u64,
+) -> Result<FeeAccrual, VaultError> {
+    if total_shares == 0 {
+        return Ok(FeeAccrual {
+            management_fee_shares: 0,
+            performance_fee_shares: 0,
+            high_water_mark,
+        });
+    }
+
+    let management_fee_shares = calculate_management_fee_shares(total_shares, management_fee_bps, elapsed)?;
+    let shares_after_management = total_shares
+        .checked_add(management_fee_shares)
+        .ok_or(VaultError::ArithmeticOverflow)?;
+
+    let performance_fee_shares = calculate_performance_fee_shares(
+        total_value,
+        shares_after_management,
+        high_water_mark,
+        performance_fee_bps,
+    )?;
+    let shares_after_fees = shares_after_management
+        .checked_add(performance_fee_shares)
+        .ok_or(VaultError::ArithmeticOverflow)?;
+
+    let value_per_share = calculate_value_per_share(total_value, shares_after_fees)?;
+
+    Ok(FeeAccrual {
+        management_fee_shares,
+        performance_fee_shares,
+        high_water_mark: high_water_mark.max(value_per_share),
+    })
+}
+
+// Fee in bps of an amount of shares or tokens. Fees kept back from a user round
+// up, bounties paid out of the vault round down.
+pub fn calculate_fee_amount(amount: u64, fee_bps: u16, rounding: Rounding) -> Result<u64, VaultError> {
+    calculate_proportional_amount(amount, fee_bps as u64, BPS_DENOMINATOR, rounding)
+}
+
+// Accrue fees since the last accrual and return the fee shares to mint
+pub fn accrue_fees(vault: &mut Vault, total_value: u128, total_shares: u64, now: i64) -> Result<u64, VaultError> {
+    let elapsed = now.saturating_sub(vault.last_fee_accrual).max(0) as u64;
+    let accrual = calculate_fee_accrual(
+        total_value,
+        total_shares,
+        vault.high_water_mark,
+        vault.management_fee_bps,
+        vault.performance_fee_bps,
+        elapsed,
+    )?;
+
+    vault.high_water_mark = accrual.high_water_mark;
+    vault.last_fee_accrual = now;
+    accrual.total_shares()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ONE_SHARE: u64 = 1_000_000;
+
+    #[test]
+    fn management_fee_is_zero_without_elapsed_time_or_rate() {
+        assert_eq!(calculate_management_fee_shares(1_000 * ONE_SHARE, 200, 0).unwrap(), 0);
+        assert_eq!(calculate_management_fee_shares(1_000 * ONE_SHARE, 0, SECONDS_PER_YEAR).unwrap(), 0);
+        assert_eq!(calculate_management_fee_shares(0, 200, SECONDS_PER_YEAR).unwrap(), 0);
+    }
+
+    #[test]
+    fn management_fee_dilutes_to_the_annual_rate() {
+        let total_shares = 1_000 * ONE_SHARE;
+        let fee_shares = calculate_management_fee_shares(total_shares, 200, SECONDS_PER_YEAR).unwrap();
+
+        // The recipient owns 2% of the supply after a year
+        let owned_bps = fee_shares as u128 * BPS_DENOMINATOR as u128 / (total_shares + fee_shares) as u128;
+        assert_eq!(owned_bps, 199);
+        assert_eq!(fee_shares, 20_408_163);
+    }
+
+    #[test]
+    fn management_fee_accrues_linearly_over_short_periods() {
+        let total_shares = 1_000 * ONE_SHARE;
+        let one_day = calculate_management_fee_shares(total_shares, 200, 24 * 60 * 60).unwrap();
+        let two_days = calculate_management_fee_shares(total_shares, 200, 2 * 24 * 60 * 60).unwrap();
+        // Compounding of the dilution stays below 0.01% over short periods
+        assert!(two_days >= 2 * one_day);
+        assert!((two_days - 2 * one_day) * 10_000 <= two_days);
+    }
+
+    #[test]
+    fn management_fee_rejects_a_full_dilution() {
+        assert!(matches!(
+            calculate_management_fee_shares(ONE_SHARE, 10_000, SECONDS_PER_YEAR),
+            Err(VaultError::CalculationFailure)
+        ));
+    }
+
+    #[test]
+    fn performance_fee_is_zero_at_or_below_the_high_water_mark() {
+        let total_shares = 100 * ONE_SHARE;
+        let high_water_mark = calculate_value_per_share(1_000_000, total_shares).unwrap();
+
+        assert_eq!(calculate_performance_fee_shares(1_000_000, total_shares, high_water_mark, 2_000).unwrap(), 0);
+        assert_eq!(calculate_performance_fee_shares(900_000, total_shares, high_water_mark, 2_000).unwrap(), 0);
+    }
+
+    #[test]
+    fn performance_fee_takes_its_cut_of_the_gain() {
+        let total_shares = 100 * ONE_SHARE;
+        let high_water_mark = calculate_value_per_share(1_000_000_000, total_shares).unwrap();
+
+        // 10% gain, 20% performance fee: the recipient ends up owning 2% of the value
+        let total_value = 1_100_000_000;
+        let fee_shares = calculate_performance_fee_shares(total_value, total_shares, high_water_mark, 2_000).unwrap();
+        let fee_value = total_value * fee_shares as u128 / (total_shares + fee_shares) as u128;
+        assert!((19_999_990..=20_000_000).contains(&fee_value));
+    }
+
+    #[test]
+    fn fee_accrual_initialises_the_high_water_mark_without_a_fee() {
+        let total_shares = 100 * ONE_SHARE;
+        let accrual = calculate_fee_accrual(1_000_000_000, total_shares, 0, 0, 2_000, 0).unwrap();
+
+        assert_eq!(accrual.performance_fee_shares, 0);
+        assert_eq!(accrual.high_water_mark, calculate_value_per_share(1_000_000_000, total_shares).unwrap());
+    }
+
+    #[test]
+    fn fee_accrual_does_not_charge_the_same_gain_twice() {
+        let total_shares = 100 * ONE_SHARE;
+        let high_water_mark = calculate_value_per_share(1_000_000_000, total_shares).unwrap();
+
+        let first = calculate_fee_accrual(1_100_000_000, total_shares, high_water_mark, 0, 2_000, 0).unwrap();
+        assert!(first.performance_fee_shares > 0);
+
+        let shares_after_fees = total_shares + first.total_shares().unwrap();
+        let second = calculate_fee_accrual(1_100_000_000, shares_after_fees, first.high_water_mark, 0, 2_000, 0).unwrap();
+        assert_eq!(second.performance_fee_shares, 0);
+        assert_eq!(second.high_water_mark, first.high_water_mark);
+    }
+
+    #[test]
+    fn fee_amount_rounds_down() {
+        assert_eq!(calculate_fee_amount(1_000_000, 30, Rounding::Down).unwrap(), 3_000);
+        assert_eq!(calculate_fee_amount(333, 30, Rounding::Down).unwrap(), 0);
+        assert_eq!(calculate_fee_amount(u64::MAX, 10_000, Rounding::Down).unwrap(), u64::MAX);
+        assert_eq!(calculate_fee_amount(1_000_000, 0, Rounding::Down).unwrap(), 0);
+    }
+
+    #[test]
+    fn fee_amount_rounds_up_when_kept_back() {
+        assert_eq!(calculate_fee_amount(1_000_000, 30, Rounding::Up).unwrap(), 3_000);
+        assert_eq!(calculate_fee_amount(333, 30, Rounding::Up).unwrap(), 1);
+        assert_eq!(calculate_fee_amount(u64::MAX, 10_000, Rounding::Up).unwrap(), u64::MAX);
+        assert_eq!(calculate_fee_amount(1_000_000, 0, Rounding::Up).unwrap(), 0);
+    }
+
+    #[test]
+    fn fee_accrual_skips_an_empty_vault() {
+        let accrual = calculate_fee_accrual(0, 0, 42, 200, 2_000, SECONDS_PER_YEAR).unwrap();
+        assert_eq!(accrual.total_shares().unwrap(), 0);
+        assert_eq!(accrual.high_water_mark, 42);
+    }
+}
@@ -1,41 +1,168 @@
+use ethnum::U256;
+
 use crate::errors::VaultError;
 
+// Which way a division rounds. Amounts paid out to users and shares minted to
+// them round down, shares burned from them round up, so rounding dust always
+// stays with the vault.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    Down,
+    Up,
+}
+
+// Fixed-point amount with 256 bits of headroom, so a u64 times a u128 never overflows
 #[derive(Debug)]
-pub struct TokenAmount(u128);
+pub struct TokenAmount(U256);
 
 impl TokenAmount {
     pub fn new(amount: u64) -> Self {
-        Self(amount as u128)
+        Self(U256::from(amount))
     }
 
-    pub fn multiply(&self, other: u64) -> Result<Self, VaultError> {
-        self.0
-            .checked_mul(other as u128)
-            .map(Self)
-            .ok_or(VaultError::ArithmeticOverflow)
+    pub fn from_u128(amount: u128) -> Self {
+        Self(U256::from(amount))
     }
 
-    pub fn divide(&self, other: u64) -> Result<Self, VaultError> {
+    pub fn multiply(&self, other: u128) -> Result<Self, VaultError> {
         self.0
-            .checked_div(other as u128)
+            .checked_mul(U256::from(other))
             .map(Self)
             .ok_or(VaultError::ArithmeticOverflow)
     }
 
+    pub fn divide(&self, other: u128, rounding: Rounding) -> Result<Self, VaultError> {
+        if other == 0 {
+            return Err(VaultError::CalculationFailure);
+        }
+        let other = U256::from(other);
+        let quotient = self.0 / other;
+        match rounding {
+            Rounding::Up if self.0 % other != U256::ZERO => Ok(Self(quotient + 1)),
+            _ => Ok(Self(quotient)),
+        }
+    }
+
     pub fn to_u64(&self) -> Result<u64, VaultError> {
-        self.0
-            .try_into()
-            .map_err(|_| VaultError::ArithmeticOverflow)
+        u64::try_from(self.0).map_err(|_| VaultError::ArithmeticOverflow)
     }
+
+    pub fn to_u128(&self) -> Result<u128, VaultError> {
+        u128::try_from(self.0).map_err(|_| VaultError::ArithmeticOverflow)
+    }
+}
+
+// amount * numerator / denominator, multiplying before dividing
+pub fn mul_div(amount: u128, numerator: u128, denominator: u128, rounding: Rounding) -> Result<u128, VaultError> {
+    TokenAmount::from_u128(amount)
+        .multiply(numerator)?
+        .divide(denominator, rounding)?
+        .to_u128()
 }
 
 pub fn calculate_proportional_amount(
     amount: u64,
     numerator: u64,
     denominator: u64,
+    rounding: Rounding,
 ) -> Result<u64, VaultError> {
     TokenAmount::new(amount)
-        .multiply(numerator)?
-        .divide(denominator)?
+        .multiply(numerator as u128)?
+        .divide(denominator as u128, rounding)?
         .to_u64()
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::test_utils::XorShift;
+
+    const CASES: usize = 10_000;
+
+    #[test]
+    fn rounding_brackets_the_exact_quotient() {
+        let mut rng = XorShift(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..CASES {
+            let amount = rng.below(u32::MAX as u64) as u128;
+            let numerator = rng.below(u32::MAX as u64) as u128;
+            let denominator = rng.below(u32::MAX as u64) as u128 + 1;
+
+            let down = mul_div(amount, numerator, denominator, Rounding::Down).unwrap();
+            let up = mul_div(amount, numerator, denominator, Rounding::Up).unwrap();
+            let product = amount * numerator;
+            assert!(down * denominator <= product);
+            assert!(up * denominator >= product);
+            assert!(up - down <= 1);
+            assert_eq!(up == down, product % denominator == 0);
+        }
+    }
+
+    #[test]
+    fn multiplies_before_dividing_without_overflow() {
+        assert_eq!(
+            calculate_proportional_amount(u64::MAX, u64::MAX, u64::MAX, Rounding::Down).unwrap(),
+            u64::MAX
+        );
+        assert_eq!(calculate_proportional_amount(1, 3, 2, Rounding::Down).unwrap(), 1);
+        assert_eq!(calculate_proportional_amount(1, 3, 2, Rounding::Up).unwrap(), 2);
+    }
+
+    #[test]
+    fn division_by_zero_is_an_error() {
+        assert!(matches!(
+            calculate_proportional_amount(1, 1, 0, Rounding::Down),
+            Err(VaultError::CalculationFailure)
+        ));
+        assert!(matches!(mul_div(1, 1, 0, Rounding::Up), Err(VaultError::CalculationFailure)));
+    }
+
+    #[test]
+    fn payouts_never_exceed_the_vault_balance() {
+        let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+        for _ in 0..CASES / 10 {
+            let total_amount = rng.below(1 << 50);
+            let mut remaining_shares = rng.below(1 << 50) + 1;
+            let mut remaining_amount = total_amount;
+            let mut paid = 0u64;
+
+            // Holders redeem random slices of the supply one after another
+            while remaining_shares > 0 {
+                let shares = rng.below(remaining_shares) + 1;
+                let payout = calculate_proportional_amount(remaining_amount, shares, remaining_shares, Rounding::Down)
+                    .unwrap();
+                // Nobody gets more than their pro-rata slice of what is left
+                assert!(payout as u128 * remaining_shares as u128 <= remaining_amount as u128 * shares as u128);
+                remaining_amount -= payout;
+                remaining_shares -= shares;
+                paid += payout;
+            }
+            assert!(paid <= total_amount);
+        }
+    }
+
+    #[test]
+    fn deposit_then_withdraw_never_returns_more_than_deposited() {
+        let mut rng = XorShift(0x1234_5678_9abc_def1);
+        for _ in 0..CASES {
+            let total_value = rng.below(1 << 60) as u128 + 1;
+            let total_shares = rng.below(1 << 60) as u128 + 1;
+            let deposit_value = rng.below(1 << 60) as u128;
+
+            // Shares minted to the depositor round down
+            let shares = mul_div(deposit_value, total_shares, total_value, Rounding::Down).unwrap();
+            // Redeeming them straight away pays out rounded down
+            let payout = mul_div(total_value + deposit_value, shares, total_shares + shares, Rounding::Down).unwrap();
+            assert!(payout <= deposit_value);
+
+            // Rounding up on withdrawal never burns fewer shares than the exact
+            // amount for the value paid out, and since that payout is worth at
+            // most the minted shares, never more than were minted
+            let burned = mul_div(payout, total_shares + shares, total_value + deposit_value, Rounding::Up).unwrap();
+            assert!(
+                U256::from(burned) * U256::from(total_value + deposit_value)
+                    >= U256::from(payout) * U256::from(total_shares + shares)
+            );
+            assert!(burned <= shares);
+        }
+    }
+}
@@ -2,4 +2,22 @@ pub mod utils;
 pub use utils::*;
 
 pub mod math;
-pub use math::*;
\ No newline at end of file
+pub use math::*;
+
+pub mod oracle;
+pub use oracle::*;
+
+pub mod twap;
+pub use twap::*;
+
+pub mod price_source;
+pub use price_source::*;
+
+pub mod fees;
+pub use fees::*;
+
+pub mod shares;
+pub use shares::*;
+
+#[cfg(test)]
+mod test_utils;
@@ -0,0 +1,201 @@
+use anchor_lang::{prelude::*, solana_program::pubkey};
+use ethnum::U256;
+use orca_whirlpools_core::tick_index_to_sqrt_price;
+
+use crate::{errors::VaultError, state::{PricingMode, Vault, SHARE_DECIMALS}};
+
+// Pyth v2 oracle program, the only owner a price account is read from
+pub const PYTH_PROGRAM_ID: Pubkey = pubkey!("FsJ3A3u2vn5cTVofAjvy6y5kwABJAqYWpe4975bi9epH");
+
+// Layout of a Pyth v2 price account
+const PYTH_MAGIC: u32 = 0xa1b2c3d4;
+const PYTH_VERSION_2: u32 = 2;
+const PYTH_ACCOUNT_TYPE_PRICE: u32 = 3;
+const PYTH_STATUS_TRADING: u32 = 1;
+const PYTH_PRICE_ACCOUNT_LEN: usize = 3312;
+
+const PYTH_EXPO_OFFSET: usize = 20;
+const PYTH_TIMESTAMP_OFFSET: usize = 96;
+const PYTH_AGG_PRICE_OFFSET: usize = 208;
+const PYTH_AGG_CONF_OFFSET: usize = 216;
+const PYTH_AGG_STATUS_OFFSET: usize = 224;
+
+// Switchboard On-Demand program, the only owner a pull feed is read from
+pub const SWITCHBOARD_ON_DEMAND_PROGRAM_ID: Pubkey = pubkey!("SBondMDrcV3K4kxZR1HNVT7osZxAHVHgYXL5Ze1oMUv");
+
+// Layout of a Switchboard On-Demand pull feed account
+const SWITCHBOARD_PULL_FEED_DISCRIMINATOR: [u8; 8] = [196, 27, 108, 196, 10, 215, 219, 40];
+const SWITCHBOARD_LAST_UPDATE_TIMESTAMP_OFFSET: usize = 8 + 2208;
+const SWITCHBOARD_RESULT_VALUE_OFFSET: usize = 8 + 2256;
+const SWITCHBOARD_RESULT_STD_DEV_OFFSET: usize = 8 + 2272;
+const SWITCHBOARD_RESULT_NUM_SAMPLES_OFFSET: usize = 8 + 2352;
+const SWITCHBOARD_MIN_ACCOUNT_LEN: usize = 8 + 2384;
+// Feed values carry 18 decimals, keep 9 of them so prices fit in an i64
+const SWITCHBOARD_PRECISION: u32 = 18;
+const SWITCHBOARD_EXPO: i32 = -9;
+
+#[derive(Debug)]
+pub struct OraclePrice {
+    pub price: i64,
+    pub conf: u64,
+    pub expo: i32,
+    pub publish_time: i64,
+}
+
+fn read_bytes<const N: usize>(data: &[u8], offset: usize) -> [u8; N] {
+    let mut bytes = [0u8; N];
+    bytes.copy_from_slice(&data[offset..offset + N]);
+    bytes
+}
+
+pub fn load_pyth_price(price_feed: &AccountInfo) -> Result<OraclePrice> {
+    // Anyone can create an account with the same layout, only trust Pyth's
+    require_keys_eq!(*price_feed.owner, PYTH_PROGRAM_ID, VaultError::InvalidPriceFeed);
+    let data = price_feed.try_borrow_data()?;
+    require!(data.len() >= PYTH_PRICE_ACCOUNT_LEN, VaultError::InvalidPriceFeed);
+
+    let magic = u32::from_le_bytes(read_bytes(&data, 0));
+    let version = u32::from_le_bytes(read_bytes(&data, 4));
+    let account_type = u32::from_le_bytes(read_bytes(&data, 8));
+    require!(
+        magic == PYTH_MAGIC && version == PYTH_VERSION_2 && account_type == PYTH_ACCOUNT_TYPE_PRICE,
+        VaultError::InvalidPriceFeed
+    );
+
+    let status = u32::from_le_bytes(read_bytes(&data, PYTH_AGG_STATUS_OFFSET));
+    require!(status == PYTH_STATUS_TRADING, VaultError::InvalidPriceFeed);
+
+    Ok(OraclePrice {
+        price: i64::from_le_bytes(read_bytes(&data, PYTH_AGG_PRICE_OFFSET)),
+        conf: u64::from_le_bytes(read_bytes(&data, PYTH_AGG_CONF_OFFSET)),
+        expo: i32::from_le_bytes(read_bytes(&data, PYTH_EXPO_OFFSET)),
+        publish_time: i64::from_le_bytes(read_bytes(&data, PYTH_TIMESTAMP_OFFSET)),
+    })
+}
+
+pub fn load_switchboard_price(price_feed: &AccountInfo) -> Result<OraclePrice> {
+    // The raw offsets below mean nothing in an account Switchboard does not own
+    require_keys_eq!(*price_feed.owner, SWITCHBOARD_ON_DEMAND_PROGRAM_ID, VaultError::InvalidPriceFeed);
+    let data = price_feed.try_borrow_data()?;
+    require!(data.len() >= SWITCHBOARD_MIN_ACCOUNT_LEN, VaultError::InvalidPriceFeed);
+    require!(
+        data[..8] == SWITCHBOARD_PULL_FEED_DISCRIMINATOR,
+        VaultError::InvalidPriceFeed
+    );
+
+    let num_samples = data[SWITCHBOARD_RESULT_NUM_SAMPLES_OFFSET];
+    require!(num_samples > 0, VaultError::InvalidPriceFeed);
+
+    let scale = 10i128.pow(SWITCHBOARD_PRECISION - SWITCHBOARD_EXPO.unsigned_abs());
+    let value = i128::from_le_bytes(read_bytes(&data, SWITCHBOARD_RESULT_VALUE_OFFSET)) / scale;
+    let std_dev = i128::from_le_bytes(read_bytes(&data, SWITCHBOARD_RESULT_STD_DEV_OFFSET)) / scale;
+
+    Ok(OraclePrice {
+        price: i64::try_from(value).map_err(|_| VaultError::ArithmeticOverflow)?,
+        conf: u64::try_from(std_dev).map_err(|_| VaultError::InvalidPriceFeed)?,
+        expo: SWITCHBOARD_EXPO,
+        publish_time: i64::from_le_bytes(read_bytes(&data, SWITCHBOARD_LAST_UPDATE_TIMESTAMP_OFFSET)),
+    })
+}
+
+pub fn validate_oracle_price(
+    oracle_price: &OraclePrice,
+    now: i64,
+    max_price_age: u64,
+    max_confidence_bps: u16,
+) -> Result<u64> {
+    require!(oracle_price.price > 0, VaultError::InvalidPriceFeed);
+    let price = oracle_price.price as u64;
+
+    let age = now.saturating_sub(oracle_price.publish_time).max(0) as u64;
+    require!(age <= max_price_age, VaultError::StalePrice);
+
+    // conf / price must stay within max_confidence_bps
+    let conf_bps = (oracle_price.conf as u128)
+        .checked_mul(10_000)
+        .ok_or(VaultError::ArithmeticOverflow)?
+        / price as u128;
+    require!(conf_bps <= max_confidence_bps as u128, VaultError::PriceConfidenceTooWide);
+
+    Ok(price)
+}
+
+// Scale a price of `price * 10^expo` per whole token into the per-base-unit price
+// calculate_shares expects, so that one share is minted per unit of quote value
+pub fn normalize_price(
+    price: u64,
+    expo: i32,
+    token_decimal: u8,
+    max_decimal: u8,
+) -> Result<u64> {
+    let exponent = expo + SHARE_DECIMALS as i32 + max_decimal as i32 - token_decimal as i32;
+    let scale = 10u128
+        .checked_pow(exponent.unsigned_abs())
+        .ok_or(VaultError::ArithmeticOverflow)?;
+
+    let normalized = if exponent >= 0 {
+        (price as u128).checked_mul(scale)
+    } else {
+        (price as u128).checked_div(scale)
+    }
+    .ok_or(VaultError::ArithmeticOverflow)?;
+
+    Ok(u64::try_from(normalized).map_err(|_| VaultError::ArithmeticOverflow)?)
+}
+
+pub fn get_oracle_price(
+    oracle_price: &OraclePrice,
+    now: i64,
+    max_price_age: u64,
+    max_confidence_bps: u16,
+    token_decimal: u8,
+    max_decimal: u8,
+) -> Result<u64> {
+    let price = validate_oracle_price(oracle_price, now, max_price_age, max_confidence_bps)?;
+    normalize_price(price, oracle_price.expo, token_decimal, max_decimal)
+}
+
+// Price of token A in token B base units as Q64.64
+pub fn sqrt_price_to_price_x64(sqrt_price: u128) -> Result<u128> {
+    let sqrt_price = U256::from(sqrt_price);
+    Ok(u128::try_from((sqrt_price * sqrt_price) >> 64).map_err(|_| VaultError::ArithmeticOverflow)?)
+}
+
+pub fn price_ratio_x64(price_a: u64, price_b: u64) -> Result<u128> {
+    require!(price_b > 0, VaultError::InvalidPriceFeed);
+    Ok(((price_a as u128) << 64) / price_b as u128)
+}
+
+// Price the live pool is checked against: the oracle price in oracle modes, the
+// admin reference tick in Whirlpool mode and the TWAP in Twap mode
+pub fn get_reference_price_x64(
+    vault: &Vault,
+    valuation_sqrt_price: u128,
+    oracle_prices: Option<(u64, u64)>,
+) -> Result<u128> {
+    match vault.pricing_mode {
+        PricingMode::Pyth | PricingMode::Switchboard => {
+            let (token_a_price, token_b_price) = oracle_prices.ok_or(VaultError::MissingPriceFeed)?;
+            price_ratio_x64(token_a_price, token_b_price)
+        }
+        PricingMode::Whirlpool => sqrt_price_to_price_x64(tick_index_to_sqrt_price(vault.reference_tick)),
+        PricingMode::Twap => sqrt_price_to_price_x64(valuation_sqrt_price),
+    }
+}
+
+pub fn check_price_deviation(
+    reference_price_x64: u128,
+    pool_price_x64: u128,
+    max_price_deviation_bps: u16,
+) -> Result<()> {
+    require!(reference_price_x64 > 0, VaultError::CalculationFailure);
+
+    let deviation = reference_price_x64.abs_diff(pool_price_x64);
+    let deviation_bps = U256::from(deviation) * U256::from(10_000u32) / U256::from(reference_price_x64);
+    require!(
+        deviation_bps <= U256::from(max_price_deviation_bps),
+        VaultError::PriceDeviationTooHigh
+    );
+
+    Ok(())
+}
@@ -0,0 +1,98 @@
+use anchor_lang::prelude::*;
+
+use crate::{errors::VaultError, state::{PricingMode, Vault}, util::{get_oracle_price, load_pyth_price, load_switchboard_price, OraclePrice}};
+
+// Where deposit and withdraw get token prices from, picked per vault at initialization
+pub enum PriceSource<'a, 'info> {
+    Pyth {
+        token_a_price_feed: &'a UncheckedAccount<'info>,
+        token_b_price_feed: &'a UncheckedAccount<'info>,
+    },
+    Switchboard {
+        token_a_price_feed: &'a UncheckedAccount<'info>,
+        token_b_price_feed: &'a UncheckedAccount<'info>,
+    },
+    // Valued from the pool itself, no external prices
+    Whirlpool,
+    Twap,
+}
+
+impl<'a, 'info> PriceSource<'a, 'info> {
+    pub fn new(
+        pricing_mode: PricingMode,
+        token_a_price_feed: Option<&'a UncheckedAccount<'info>>,
+        token_b_price_feed: Option<&'a UncheckedAccount<'info>>,
+    ) -> Result<Self> {
+        Ok(match pricing_mode {
+            PricingMode::Pyth => PriceSource::Pyth {
+                token_a_price_feed: token_a_price_feed.ok_or(VaultError::MissingPriceFeed)?,
+                token_b_price_feed: token_b_price_feed.ok_or(VaultError::MissingPriceFeed)?,
+            },
+            PricingMode::Switchboard => PriceSource::Switchboard {
+                token_a_price_feed: token_a_price_feed.ok_or(VaultError::MissingPriceFeed)?,
+                token_b_price_feed: token_b_price_feed.ok_or(VaultError::MissingPriceFeed)?,
+            },
+            PricingMode::Whirlpool => PriceSource::Whirlpool,
+            PricingMode::Twap => PriceSource::Twap,
+        })
+    }
+
+    // Keys of the feeds to record on the vault
+    pub fn price_feeds(&self) -> (Pubkey, Pubkey) {
+        match self {
+            PriceSource::Pyth { token_a_price_feed, token_b_price_feed }
+            | PriceSource::Switchboard { token_a_price_feed, token_b_price_feed } => {
+                (token_a_price_feed.key(), token_b_price_feed.key())
+            }
+            PriceSource::Whirlpool | PriceSource::Twap => (Pubkey::default(), Pubkey::default()),
+        }
+    }
+
+    // Raw feed prices, also used to check the feeds at initialization
+    pub fn load_prices(&self) -> Result<Option<(OraclePrice, OraclePrice)>> {
+        Ok(match self {
+            PriceSource::Pyth { token_a_price_feed, token_b_price_feed } => Some((
+                load_pyth_price(token_a_price_feed)?,
+                load_pyth_price(token_b_price_feed)?,
+            )),
+            PriceSource::Switchboard { token_a_price_feed, token_b_price_feed } => Some((
+                load_switchboard_price(token_a_price_feed)?,
+                load_switchboard_price(token_b_price_feed)?,
+            )),
+            PriceSource::Whirlpool | PriceSource::Twap => None,
+        })
+    }
+
+    // Normalized prices of both vault tokens, None when the vault is valued from the pool
+    pub fn token_prices(&self, vault: &Vault) -> Result<Option<(u64, u64)>> {
+        // Prices are only read from the feeds recorded on the vault
+        require!(
+            self.price_feeds() == (vault.token_a_price_feed, vault.token_b_price_feed),
+            VaultError::InvalidPriceFeed
+        );
+        let Some((token_a_oracle_price, token_b_oracle_price)) = self.load_prices()? else {
+            return Ok(None);
+        };
+
+        let max_decimal = vault.token_a_decimal.max(vault.token_b_decimal);
+        let now = Clock::get()?.unix_timestamp;
+        let token_a_price = get_oracle_price(
+            &token_a_oracle_price,
+            now,
+            vault.max_price_age,
+            vault.max_confidence_bps,
+            vault.token_a_decimal,
+            max_decimal,
+        )?;
+        let token_b_price = get_oracle_price(
+            &token_b_oracle_price,
+            now,
+            vault.max_price_age,
+            vault.max_confidence_bps,
+            vault.token_b_decimal,
+            max_decimal,
+        )?;
+
+        Ok(Some((token_a_price, token_b_price)))
+    }
+}
@@ -0,0 +1,102 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Burn, FreezeAccount, MintTo, ThawAccount, TokenAccount};
+
+// Shares are non-transferable: every account the vault mints shares into is kept
+// frozen and only thawed for the vault's own mints and burns. Otherwise the same
+// shares could be recorded on several reward checkpoints by passing them around.
+
+fn is_frozen(account: &AccountInfo) -> Result<bool> {
+    Ok(TokenAccount::try_deserialize(&mut &account.try_borrow_data()?[..])?.is_frozen())
+}
+
+fn thaw_shares<'info>(
+    token_program: &AccountInfo<'info>,
+    share_mint: &AccountInfo<'info>,
+    account: &AccountInfo<'info>,
+    vault: &AccountInfo<'info>,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    token::thaw_account(CpiContext::new_with_signer(
+        token_program.clone(),
+        ThawAccount {
+            account: account.clone(),
+            mint: share_mint.clone(),
+            authority: vault.clone(),
+        },
+        signer_seeds,
+    ))
+}
+
+fn freeze_shares<'info>(
+    token_program: &AccountInfo<'info>,
+    share_mint: &AccountInfo<'info>,
+    account: &AccountInfo<'info>,
+    vault: &AccountInfo<'info>,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    token::freeze_account(CpiContext::new_with_signer(
+        token_program.clone(),
+        FreezeAccount {
+            account: account.clone(),
+            mint: share_mint.clone(),
+            authority: vault.clone(),
+        },
+        signer_seeds,
+    ))
+}
+
+pub fn mint_shares<'info>(
+    token_program: AccountInfo<'info>,
+    share_mint: AccountInfo<'info>,
+    to: AccountInfo<'info>,
+    vault: AccountInfo<'info>,
+    signer_seeds: &[&[&[u8]]],
+    amount: u64,
+) -> Result<()> {
+    if is_frozen(&to)? {
+        thaw_shares(&token_program, &share_mint, &to, &vault, signer_seeds)?;
+    }
+    token::mint_to(
+        CpiContext::new_with_signer(
+            token_program.clone(),
+            MintTo {
+                mint: share_mint.clone(),
+                to: to.clone(),
+                authority: vault.clone(),
+            },
+            signer_seeds,
+        ),
+        amount,
+    )?;
+    freeze_shares(&token_program, &share_mint, &to, &vault, signer_seeds)
+}
+
+pub fn burn_shares<'info>(
+    token_program: AccountInfo<'info>,
+    share_mint: AccountInfo<'info>,
+    from: AccountInfo<'info>,
+    owner: AccountInfo<'info>,
+    vault: AccountInfo<'info>,
+    signer_seeds: &[&[&[u8]]],
+    amount: u64,
+) -> Result<()> {
+    if is_frozen(&from)? {
+        thaw_shares(&token_program, &share_mint, &from, &vault, signer_seeds)?;
+    }
+    token::burn(
+        CpiContext::new(
+            token_program.clone(),
+            Burn {
+                mint: share_mint.clone(),
+                from: from.clone(),
+                authority: owner,
+            },
+        ),
+        amount,
+    )?;
+    // An emptied account is left thawed so its owner can close it
+    if token::accessor::amount(&from)? > 0 {
+        freeze_shares(&token_program, &share_mint, &from, &vault, signer_seeds)?;
+    }
+    Ok(())
+}
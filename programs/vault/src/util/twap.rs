@@ -0,0 +1,54 @@
+use anchor_lang::prelude::*;
+use orca_whirlpools_core::tick_index_to_sqrt_price;
+use whirlpool_cpi::state::Whirlpool;
+
+use crate::{errors::VaultError, state::{Observation, ObservationState, PricingMode, Vault}};
+
+// Time-weighted average tick over the last `window` slots, from the tick
+// cumulatives at the start of the window and at `current_slot`
+pub fn calculate_twap_tick(
+    observations: &[Observation],
+    current_slot: u64,
+    window: u64,
+) -> Result<i32> {
+    let window_start = current_slot.saturating_sub(window);
+    let newest = observations.last().ok_or(VaultError::InsufficientObservations)?;
+    // Last observation at or before the start of the window
+    let start = observations
+        .iter()
+        .rev()
+        .find(|observation| observation.slot <= window_start)
+        .ok_or(VaultError::InsufficientObservations)?;
+
+    // A zero window collapses to the newest observation
+    if window == 0 {
+        return Ok(newest.tick_current_index);
+    }
+
+    let tick_cumulative_delta = newest
+        .tick_cumulative_at(current_slot)?
+        .checked_sub(start.tick_cumulative_at(window_start)?)
+        .ok_or(VaultError::ArithmeticOverflow)?;
+    Ok(tick_cumulative_delta.div_euclid(window as i128) as i32)
+}
+
+// Tick and sqrt_price the vault is valued at: the TWAP in Twap pricing mode,
+// the pool spot price otherwise
+pub fn get_valuation_price(
+    vault: &Vault,
+    whirlpool: &Whirlpool,
+    observation_state: Option<&Account<ObservationState>>,
+) -> Result<(i32, u128)> {
+    if vault.pricing_mode != PricingMode::Twap {
+        return Ok((whirlpool.tick_current_index, whirlpool.sqrt_price));
+    }
+
+    let observation_state = observation_state.ok_or(VaultError::MissingObservationState)?;
+    let twap_tick = calculate_twap_tick(
+        &observation_state.ordered(),
+        Clock::get()?.slot,
+        vault.twap_window,
+    )?;
+
+    Ok((twap_tick, tick_index_to_sqrt_price(twap_tick)))
+}
@@ -1,35 +1,125 @@
-use orca_whirlpools_core::{tick_index_to_sqrt_price, try_get_amount_delta_a, try_get_amount_delta_b};
-
-use crate::errors::VaultError;
-
-pub fn calculate_shares(amount_a: u64, amount_b: u64, total_token_a_amount: u64, total_token_b_amount: u64, price_a: u64, price_b: u64, total_shares: u64, decimal: u8) -> u64 {
-    let amount_a_u128 = amount_a as u128;
-    let amount_b_u128 = amount_b as u128;
-    let total_token_a_amount_u128 = total_token_a_amount as u128;
-    let total_token_b_amount_u128 = total_token_b_amount as u128;
-    let price_a_u128 = price_a as u128;
-    let price_b_u128 = price_b as u128;
-    let total_shares_u128 = total_shares as u128;
-    let share_amount =     
-        if total_shares == 0 {
-            (amount_a_u128.checked_mul(price_a_u128).unwrap())
-            .checked_add(amount_b_u128.checked_mul(price_b_u128).unwrap())
-            .unwrap()
-        } else {
-            let deposit_value = (amount_a_u128.checked_mul(price_a_u128).unwrap())
-                .checked_add(amount_b_u128.checked_mul(price_b_u128).unwrap())
-                .unwrap();
+use ethnum::U256;
+use orca_whirlpools_core::{
+    increase_liquidity_quote_a, increase_liquidity_quote_b, tick_index_to_sqrt_price, try_get_amount_delta_a,
+    try_get_amount_delta_b, IncreaseLiquidityQuote,
+};
 
-            let total_value = (total_token_a_amount_u128.checked_mul(price_a_u128).unwrap())
-                .checked_add(total_token_b_amount_u128.checked_mul(price_b_u128).unwrap())
-                .unwrap();
+use crate::{errors::VaultError, state::{DEAD_SHARES, SHARE_DECIMALS}, util::{mul_div, Rounding, BPS_DENOMINATOR}};
 
-            deposit_value.checked_div(total_value).unwrap().checked_mul(total_shares_u128).unwrap()
-        };
-    
-    // Divide by 10^decimal
-    let divisor = 10u128.pow(decimal as u32);
-    share_amount.checked_div(divisor).unwrap() as u64
+// Tick bounds of a Whirlpool position
+pub const MIN_TICK_INDEX: i32 = -443636;
+pub const MAX_TICK_INDEX: i32 = 443636;
+// sqrt_price bounds of a Whirlpool, used as swap limits when none is given
+pub const MIN_SQRT_PRICE: u128 = 4295048016;
+pub const MAX_SQRT_PRICE: u128 = 79226673515401279992447579055;
+
+pub fn calculate_value(amount_a: u64, amount_b: u64, price_a: u64, price_b: u64) -> Result<u128, VaultError> {
+    let value_a = (amount_a as u128)
+        .checked_mul(price_a as u128)
+        .ok_or(VaultError::ArithmeticOverflow)?;
+    let value_b = (amount_b as u128)
+        .checked_mul(price_b as u128)
+        .ok_or(VaultError::ArithmeticOverflow)?;
+
+    value_a.checked_add(value_b).ok_or(VaultError::ArithmeticOverflow)
+}
+
+pub fn calculate_value_in_token_b(amount_a: u64, amount_b: u64, sqrt_price: u128) -> Result<u128, VaultError> {
+    // amount_a * (sqrt_price / 2^64)^2, shifting after each multiplication to stay within 256 bits
+    let sqrt_price = U256::from(sqrt_price);
+    let value_a = (((U256::from(amount_a) * sqrt_price) >> 64) * sqrt_price) >> 64;
+
+    u128::try_from(value_a)
+        .map_err(|_| VaultError::ArithmeticOverflow)?
+        .checked_add(amount_b as u128)
+        .ok_or(VaultError::ArithmeticOverflow)
+}
+
+// Value in the vault's unit of account: normalized oracle prices in oracle
+// modes, token B scaled so one share is minted per whole token B otherwise
+pub fn calculate_vault_value(
+    amount_a: u64,
+    amount_b: u64,
+    oracle_prices: Option<(u64, u64)>,
+    sqrt_price: u128,
+) -> Result<u128, VaultError> {
+    match oracle_prices {
+        Some((price_a, price_b)) => calculate_value(amount_a, amount_b, price_a, price_b),
+        None => calculate_value_in_token_b(amount_a, amount_b, sqrt_price)?
+            .checked_mul(10u128.pow(SHARE_DECIMALS as u32))
+            .ok_or(VaultError::ArithmeticOverflow),
+    }
+}
+
+// Shares to mint for deposit_value, priced on total_value as the vault stood
+// before the deposit. The first deposit mints one share per whole unit of value,
+// later ones their fraction of the supply, rounded down.
+pub fn calculate_shares(
+    deposit_value: u128,
+    total_value: u128,
+    total_shares: u64,
+    decimal: u8,
+) -> Result<u64, VaultError> {
+    let share_amount = if total_shares == 0 {
+        let divisor = 10u128
+            .checked_pow(decimal as u32)
+            .ok_or(VaultError::ArithmeticOverflow)?;
+        deposit_value / divisor
+    } else {
+        mul_div(deposit_value, total_shares as u128, total_value, Rounding::Down)?
+    };
+
+    u64::try_from(share_amount).map_err(|_| VaultError::ArithmeticOverflow)
+}
+
+// Shares and token amounts of a deposit that only takes the ratio the vault
+// already holds. Shares are pro rata on the limiting token and round down, the
+// amounts taken for them round up, so the excess stays with the user.
+pub fn calculate_proportional_deposit(
+    amount_a: u64,
+    amount_b: u64,
+    total_token_a_amount: u64,
+    total_token_b_amount: u64,
+    total_shares: u64,
+) -> Result<(u64, u64, u64), VaultError> {
+    if total_shares == 0 {
+        return Err(VaultError::CalculationFailure);
+    }
+
+    let shares_for = |amount: u64, total_amount: u64| -> Result<Option<u128>, VaultError> {
+        if total_amount == 0 {
+            // The vault holds none of this token, so it does not limit the deposit
+            return Ok(None);
+        }
+        mul_div(amount as u128, total_shares as u128, total_amount as u128, Rounding::Down).map(Some)
+    };
+    let share_amount = match (shares_for(amount_a, total_token_a_amount)?, shares_for(amount_b, total_token_b_amount)?) {
+        (Some(shares_a), Some(shares_b)) => Some(shares_a.min(shares_b)),
+        (shares_a, shares_b) => shares_a.or(shares_b),
+    }
+    // An empty vault has no ratio to match
+    .ok_or(VaultError::CalculationFailure)?;
+    let share_amount = u64::try_from(share_amount).map_err(|_| VaultError::ArithmeticOverflow)?;
+
+    let amount_for = |total_amount: u64| -> Result<u64, VaultError> {
+        let amount = mul_div(share_amount as u128, total_amount as u128, total_shares as u128, Rounding::Up)?;
+        u64::try_from(amount).map_err(|_| VaultError::ArithmeticOverflow)
+    };
+
+    Ok((share_amount, amount_for(total_token_a_amount)?, amount_for(total_token_b_amount)?))
+}
+
+// Splits the shares of a deposit into the dead shares locked by the first
+// deposit and the shares left for the depositor
+pub fn split_dead_shares(share_amount: u64, total_shares: u64) -> Result<(u64, u64), VaultError> {
+    if total_shares > 0 {
+        return Ok((0, share_amount));
+    }
+    if share_amount <= DEAD_SHARES {
+        return Err(VaultError::DepositTooSmall);
+    }
+
+    Ok((DEAD_SHARES, share_amount - DEAD_SHARES))
 }
 
 pub fn calculate_total_tokens(
@@ -70,4 +160,421 @@ pub fn calculate_total_tokens(
         .ok_or(VaultError::CalculationFailure)?;
 
     Ok((total_token_a_amount, total_token_b_amount))
-}
\ No newline at end of file
+}
+
+// Largest liquidity the idle token balances can fund at sqrt_price, along with
+// the token amounts it takes. Whatever the other token cannot match stays idle.
+pub fn calculate_max_liquidity(
+    token_a_amount: u64,
+    token_b_amount: u64,
+    current_tick_index: i32,
+    sqrt_price: u128,
+    tick_lower_index: i32,
+    tick_upper_index: i32,
+) -> Result<IncreaseLiquidityQuote, VaultError> {
+    let quote_a = || {
+        increase_liquidity_quote_a(token_a_amount, 0, sqrt_price, tick_lower_index, tick_upper_index, None, None)
+            .map_err(|_| VaultError::CalculationFailure)
+    };
+    let quote_b = || {
+        increase_liquidity_quote_b(token_b_amount, 0, sqrt_price, tick_lower_index, tick_upper_index, None, None)
+            .map_err(|_| VaultError::CalculationFailure)
+    };
+
+    let quote = if current_tick_index < tick_lower_index {
+        // current tick below position, only token A is deposited
+        quote_a()?
+    } else if current_tick_index < tick_upper_index {
+        // current tick inside position, the scarcer token bounds the liquidity
+        let quote_a = quote_a()?;
+        let quote_b = quote_b()?;
+        if quote_a.liquidity_delta <= quote_b.liquidity_delta {
+            quote_a
+        } else {
+            quote_b
+        }
+    } else {
+        // current tick above position, only token B is deposited
+        quote_b()?
+    };
+
+    if quote.token_max_a > token_a_amount || quote.token_max_b > token_b_amount {
+        return Err(VaultError::CalculationFailure);
+    }
+
+    Ok(quote)
+}
+
+// Position ticks must be initializable in the pool and no wider than the vault allows
+pub fn validate_position_ticks(
+    tick_lower_index: i32,
+    tick_upper_index: i32,
+    tick_spacing: u16,
+    max_position_width: u32,
+) -> Result<(), VaultError> {
+    let tick_spacing = tick_spacing as i32;
+    if tick_spacing == 0
+        || tick_lower_index >= tick_upper_index
+        || tick_lower_index < MIN_TICK_INDEX
+        || tick_upper_index > MAX_TICK_INDEX
+        || tick_lower_index % tick_spacing != 0
+        || tick_upper_index % tick_spacing != 0
+    {
+        return Err(VaultError::InvalidTickRange);
+    }
+
+    let width = ((tick_upper_index - tick_lower_index) / tick_spacing) as u32;
+    if width > max_position_width {
+        return Err(VaultError::PositionTooWide);
+    }
+
+    Ok(())
+}
+
+// Range of target_width tick spacings centred on the current tick
+pub fn calculate_target_ticks(
+    current_tick_index: i32,
+    tick_spacing: u16,
+    target_width: u32,
+) -> Result<(i32, i32), VaultError> {
+    if tick_spacing == 0 || target_width == 0 {
+        return Err(VaultError::InvalidRebalancePolicy);
+    }
+
+    let tick_spacing = tick_spacing as i64;
+    let width = target_width as i64 * tick_spacing;
+    let tick_lower_index = (current_tick_index as i64 - width / 2).div_euclid(tick_spacing) * tick_spacing;
+    let tick_upper_index = tick_lower_index + width;
+    if tick_lower_index < MIN_TICK_INDEX as i64 || tick_upper_index > MAX_TICK_INDEX as i64 {
+        return Err(VaultError::InvalidTickRange);
+    }
+
+    Ok((tick_lower_index as i32, tick_upper_index as i32))
+}
+
+// Due once the current tick is more than threshold_ticks outside the position
+pub fn is_out_of_range(
+    current_tick_index: i32,
+    tick_lower_index: i32,
+    tick_upper_index: i32,
+    threshold_ticks: u32,
+) -> bool {
+    let current_tick_index = current_tick_index as i64;
+    current_tick_index < tick_lower_index as i64 - threshold_ticks as i64
+        || current_tick_index >= tick_upper_index as i64 + threshold_ticks as i64
+}
+
+// Amount to swap, and its direction, so the idle balances match the token ratio
+// the position needs at sqrt_price. Swap fees and price impact are ignored.
+pub fn calculate_swap_to_ratio(
+    token_a_amount: u64,
+    token_b_amount: u64,
+    current_tick_index: i32,
+    sqrt_price: u128,
+    tick_lower_index: i32,
+    tick_upper_index: i32,
+) -> Result<Option<(u64, bool)>, VaultError> {
+    if current_tick_index < tick_lower_index {
+        // current tick below position, only token A is needed
+        return Ok((token_b_amount > 0).then_some((token_b_amount, false)));
+    }
+    if current_tick_index >= tick_upper_index {
+        // current tick above position, only token B is needed
+        return Ok((token_a_amount > 0).then_some((token_a_amount, true)));
+    }
+
+    // Token amounts the position takes for some liquidity, giving the target ratio
+    let quote = if token_a_amount > 0 {
+        increase_liquidity_quote_a(token_a_amount, 0, sqrt_price, tick_lower_index, tick_upper_index, None, None)
+    } else {
+        increase_liquidity_quote_b(token_b_amount, 0, sqrt_price, tick_lower_index, tick_upper_index, None, None)
+    }
+    .map_err(|_| VaultError::CalculationFailure)?;
+    if quote.token_est_a == 0 || quote.token_est_b == 0 {
+        return Ok(None);
+    }
+
+    let ratio_a = U256::from(quote.token_est_a);
+    let ratio_b = U256::from(quote.token_est_b);
+    let amount_a = U256::from(token_a_amount);
+    let amount_b = U256::from(token_b_amount);
+    let price_x64 = (U256::from(sqrt_price) * U256::from(sqrt_price)) >> 64;
+    if price_x64 == U256::ZERO {
+        return Err(VaultError::CalculationFailure);
+    }
+
+    let (amount_in, a_to_b) = if ratio_b * amount_a > ratio_a * amount_b {
+        // Too much token A: (b + x * p) / (a - x) = ratio_b / ratio_a
+        let excess = ratio_b * amount_a - ratio_a * amount_b;
+        ((excess << 64) / (ratio_a * price_x64 + (ratio_b << 64)), true)
+    } else {
+        // Too much token B: (b - y) / (a + y / p) = ratio_b / ratio_a
+        let excess = ratio_a * amount_b - ratio_b * amount_a;
+        (excess / (ratio_a + (ratio_b << 64) / price_x64), false)
+    };
+
+    let amount_in = u64::try_from(amount_in).map_err(|_| VaultError::ArithmeticOverflow)?;
+    Ok((amount_in > 0).then_some((amount_in, a_to_b)))
+}
+
+// Output of a swap at sqrt_price, before fees and price impact
+pub fn estimate_swap_output(amount_in: u64, a_to_b: bool, sqrt_price: u128) -> Result<u64, VaultError> {
+    let output = if a_to_b {
+        calculate_value_in_token_b(amount_in, 0, sqrt_price)?
+    } else {
+        let price_x64 = (U256::from(sqrt_price) * U256::from(sqrt_price)) >> 64;
+        if price_x64 == U256::ZERO {
+            return Err(VaultError::CalculationFailure);
+        }
+        u128::try_from((U256::from(amount_in) << 64) / price_x64).map_err(|_| VaultError::ArithmeticOverflow)?
+    };
+
+    u64::try_from(output).map_err(|_| VaultError::ArithmeticOverflow)
+}
+
+// Least a swap may return: the output at sqrt_price less max_slippage_bps
+pub fn calculate_min_swap_output(
+    amount_in: u64,
+    a_to_b: bool,
+    sqrt_price: u128,
+    max_slippage_bps: u16,
+) -> Result<u64, VaultError> {
+    if max_slippage_bps as u64 > BPS_DENOMINATOR {
+        return Err(VaultError::InvalidSlippage);
+    }
+
+    let expected_output = estimate_swap_output(amount_in, a_to_b, sqrt_price)?;
+    Ok((expected_output as u128 * (BPS_DENOMINATOR - max_slippage_bps as u64) as u128 / BPS_DENOMINATOR as u128) as u64)
+}
+
+// Least a swap may return priced at a reference price_x64 (token B per token A,
+// Q64.64) instead of the pool price, which can be moved right before the swap
+pub fn calculate_min_swap_output_at_price(
+    amount_in: u64,
+    a_to_b: bool,
+    price_x64: u128,
+    max_slippage_bps: u16,
+) -> Result<u64, VaultError> {
+    if max_slippage_bps as u64 > BPS_DENOMINATOR {
+        return Err(VaultError::InvalidSlippage);
+    }
+    if price_x64 == 0 {
+        return Err(VaultError::CalculationFailure);
+    }
+
+    let expected_output = if a_to_b {
+        (U256::from(amount_in) * U256::from(price_x64)) >> 64
+    } else {
+        (U256::from(amount_in) << 64) / U256::from(price_x64)
+    };
+    let min_output = expected_output * U256::from(BPS_DENOMINATOR - max_slippage_bps as u64) / U256::from(BPS_DENOMINATOR);
+    u64::try_from(min_output).map_err(|_| VaultError::ArithmeticOverflow)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::test_utils::XorShift;
+
+    const ONE_SHARE: u64 = 1_000_000;
+
+    #[test]
+    fn first_deposit_mints_one_share_per_whole_unit_of_value() {
+        // 160 whole tokens at 6 decimals of value and 9 token decimals
+        assert_eq!(calculate_shares(160_000_000_000_000_000, 0, 0, 9).unwrap(), 160 * ONE_SHARE);
+        assert_eq!(calculate_shares(999, 0, 0, 3).unwrap(), 0);
+        assert_eq!(calculate_shares(1_000, 0, 0, 0).unwrap(), 1_000);
+    }
+
+    #[test]
+    fn later_deposits_mint_their_fraction_of_the_supply() {
+        assert_eq!(calculate_shares(500, 1_000, 1_000 * ONE_SHARE, 9).unwrap(), 500 * ONE_SHARE);
+        assert_eq!(calculate_shares(2_000, 1_000, 10 * ONE_SHARE, 6).unwrap(), 20 * ONE_SHARE);
+    }
+
+    #[test]
+    fn small_deposits_are_not_truncated_to_zero() {
+        // Dividing before multiplying would mint nothing here
+        assert_eq!(calculate_shares(1, 3, 3 * ONE_SHARE, 6).unwrap(), ONE_SHARE);
+        assert_eq!(calculate_shares(1, 1_000_000_000, 1_000 * ONE_SHARE, 6).unwrap(), 1);
+    }
+
+    #[test]
+    fn share_pricing_rounds_down() {
+        assert_eq!(calculate_shares(1, 3, 2, 6).unwrap(), 0);
+        assert_eq!(calculate_shares(2, 3, 2, 6).unwrap(), 1);
+    }
+
+    #[test]
+    fn share_pricing_errors_instead_of_panicking() {
+        // Shares outstanding against a vault valued at zero
+        assert!(matches!(calculate_shares(1, 0, ONE_SHARE, 6), Err(VaultError::CalculationFailure)));
+        // More shares than fit in a u64
+        assert!(matches!(calculate_shares(u128::MAX, 1, u64::MAX, 6), Err(VaultError::ArithmeticOverflow)));
+        assert!(matches!(calculate_shares(u128::MAX, 0, 0, 0), Err(VaultError::ArithmeticOverflow)));
+        // 10^decimal does not fit in a u128
+        assert!(matches!(calculate_shares(1, 0, 0, 40), Err(VaultError::ArithmeticOverflow)));
+    }
+
+    #[test]
+    fn deposits_never_dilute_existing_holders() {
+        let mut rng = XorShift(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..10_000 {
+            let total_value = rng.below(1 << 62) + 1;
+            let total_shares = rng.below(1 << 62) + 1;
+            // Up to three times the vault, so the minted shares still fit in a u64
+            let deposit_value = rng.below(total_value * 3) as u128;
+            let total_value = total_value as u128;
+
+            let shares = calculate_shares(deposit_value, total_value, total_shares, 6).unwrap();
+
+            // The depositor's slice of the vault is worth at most what they put in:
+            // shares / (total_shares + shares) <= deposit_value / (total_value + deposit_value)
+            let new_shares = total_shares as u128 + shares as u128;
+            let new_value = total_value + deposit_value;
+            assert!(U256::from(shares) * U256::from(new_value) <= U256::from(deposit_value) * U256::from(new_shares));
+
+            // So the value per share of everybody else does not go down
+            assert!(U256::from(new_value) * U256::from(total_shares) >= U256::from(total_value) * U256::from(new_shares));
+        }
+    }
+
+    #[test]
+    fn share_pricing_is_monotonic_and_additive_within_rounding() {
+        let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+        for _ in 0..10_000 {
+            let total_value = rng.below(1 << 62) + 1;
+            let total_shares = rng.below(1 << 62) + 1;
+            // Each at most the vault's value, so even the combined deposit's shares fit in a u64
+            let first = rng.below(total_value) as u128;
+            let second = rng.below(total_value) as u128;
+            let total_value = total_value as u128;
+
+            let first_shares = calculate_shares(first, total_value, total_shares, 6).unwrap();
+            let second_shares = calculate_shares(second, total_value, total_shares, 6).unwrap();
+            let combined = calculate_shares(first + second, total_value, total_shares, 6).unwrap();
+
+            if first <= second {
+                assert!(first_shares <= second_shares);
+            }
+            // Splitting a deposit in two never mints more than depositing it at once
+            assert!(first_shares + second_shares <= combined);
+            assert!(combined - (first_shares + second_shares) <= 1);
+        }
+    }
+
+    #[test]
+    fn only_the_first_deposit_locks_dead_shares() {
+        assert_eq!(split_dead_shares(DEAD_SHARES + 1, 0).unwrap(), (DEAD_SHARES, 1));
+        assert_eq!(split_dead_shares(5, 1).unwrap(), (0, 5));
+        assert!(matches!(split_dead_shares(DEAD_SHARES, 0), Err(VaultError::DepositTooSmall)));
+        assert!(matches!(split_dead_shares(0, 0), Err(VaultError::DepositTooSmall)));
+    }
+
+    // Value a holder of `shares` can redeem, as withdraw pays it out
+    fn redeemable(shares: u64, total_shares: u64, total_value: u64) -> u64 {
+        crate::util::calculate_proportional_amount(total_value, shares, total_shares, Rounding::Down).unwrap()
+    }
+
+    #[test]
+    fn donation_steals_the_next_deposit_without_dead_shares() {
+        // The attacker mints a single share, then donates straight to the vault
+        let attacker_shares = calculate_shares(1, 0, 0, 0).unwrap();
+        let donation = 1_000_000;
+        let total_value = 1 + donation;
+
+        // The victim's deposit is worth less than one share and mints nothing
+        let victim_deposit = 1_000_000;
+        let victim_shares = calculate_shares(victim_deposit as u128, total_value as u128, attacker_shares, 0).unwrap();
+        assert_eq!(victim_shares, 0);
+
+        // The attacker redeems everything, including the victim's deposit
+        let attacker_value = redeemable(attacker_shares, attacker_shares, total_value + victim_deposit);
+        assert!(attacker_value > 1 + donation);
+    }
+
+    #[test]
+    fn dead_shares_make_the_donation_attack_unprofitable() {
+        // Smallest first deposit the vault accepts
+        let first_deposit = DEAD_SHARES + 1;
+        let share_amount = calculate_shares(first_deposit as u128, 0, 0, 0).unwrap();
+        let (dead_shares, attacker_shares) = split_dead_shares(share_amount, 0).unwrap();
+        let mut total_shares = dead_shares + attacker_shares;
+
+        let donation = 1_000_000;
+        let total_value = first_deposit + donation;
+
+        let victim_deposit = 1_000_000;
+        let victim_shares = calculate_shares(victim_deposit as u128, total_value as u128, total_shares, 0).unwrap();
+        let (_, victim_shares) = split_dead_shares(victim_shares, total_shares).unwrap();
+        assert!(victim_shares > 0);
+        total_shares += victim_shares;
+        let total_value = total_value + victim_deposit;
+
+        // The attacker loses most of the donation to the dead shares
+        let attacker_cost = first_deposit + donation;
+        let attacker_value = redeemable(attacker_shares, total_shares, total_value);
+        assert!(attacker_value < attacker_cost);
+
+        // and the victim loses far less than the attacker does
+        let victim_value = redeemable(victim_shares, total_shares, total_value);
+        assert!(victim_deposit - victim_value < (attacker_cost - attacker_value) / 100);
+    }
+
+    #[test]
+    fn proportional_deposit_takes_the_vault_ratio_and_leaves_the_excess() {
+        // The vault holds 1 A for every 2 B, the user brings too much B
+        let (shares, amount_a, amount_b) =
+            calculate_proportional_deposit(100, 500, 1_000, 2_000, 1_000 * ONE_SHARE).unwrap();
+        assert_eq!(shares, 100 * ONE_SHARE);
+        assert_eq!((amount_a, amount_b), (100, 200));
+
+        // And now too much A
+        let (shares, amount_a, amount_b) =
+            calculate_proportional_deposit(500, 100, 1_000, 2_000, 1_000 * ONE_SHARE).unwrap();
+        assert_eq!(shares, 50 * ONE_SHARE);
+        assert_eq!((amount_a, amount_b), (50, 100));
+    }
+
+    #[test]
+    fn proportional_deposit_ignores_a_token_the_vault_does_not_hold() {
+        let (shares, amount_a, amount_b) = calculate_proportional_deposit(7, 100, 0, 1_000, ONE_SHARE).unwrap();
+        assert_eq!(shares, ONE_SHARE / 10);
+        assert_eq!((amount_a, amount_b), (0, 100));
+    }
+
+    #[test]
+    fn proportional_deposit_needs_an_existing_ratio() {
+        assert!(matches!(calculate_proportional_deposit(1, 1, 0, 0, ONE_SHARE), Err(VaultError::CalculationFailure)));
+        assert!(matches!(calculate_proportional_deposit(1, 1, 1, 1, 0), Err(VaultError::CalculationFailure)));
+    }
+
+    #[test]
+    fn proportional_deposits_never_take_more_than_offered_nor_dilute() {
+        let mut rng = XorShift(0x1234_5678_9abc_def1);
+        for _ in 0..10_000 {
+            let total_a = rng.below(1 << 50);
+            let total_b = rng.below(1 << 50) + 1;
+            let total_shares = rng.below(1 << 50) + 1;
+            let amount_a = rng.below(1 << 50);
+            let amount_b = rng.below(1 << 50);
+
+            let (shares, taken_a, taken_b) =
+                calculate_proportional_deposit(amount_a, amount_b, total_a, total_b, total_shares).unwrap();
+            assert!(taken_a <= amount_a && taken_b <= amount_b);
+
+            // Each token backs the new shares at least as well as the old ones
+            let new_shares = total_shares as u128 + shares as u128;
+            assert!((total_a + taken_a) as u128 * total_shares as u128 >= total_a as u128 * new_shares);
+            assert!((total_b + taken_b) as u128 * total_shares as u128 >= total_b as u128 * new_shares);
+        }
+    }
+
+    #[test]
+    fn min_swap_output_at_a_reference_price() {
+        let two_x64 = 2u128 << 64;
+        assert_eq!(calculate_min_swap_output_at_price(1_000, true, two_x64, 100).unwrap(), 1_980);
+        assert_eq!(calculate_min_swap_output_at_price(1_000, false, two_x64, 100).unwrap(), 495);
+        assert!(matches!(calculate_min_swap_output_at_price(1_000, true, 0, 100), Err(VaultError::CalculationFailure)));
+    }
+}
@@ -11,18 +11,26 @@ import { expect } from "chai";
 import { Vault } from "../target/types/vault"; // adjust the import path
 import { TOKEN_PROGRAM_ID } from "@coral-xyz/anchor/dist/cjs/utils/token";
 import {
+  ASSOCIATED_TOKEN_PROGRAM_ID,
   createAccount,
   createMint,
   createSyncNativeInstruction,
+  getAccount,
   getAssociatedTokenAddress,
   mintTo,
+  transfer,
 } from "@solana/spl-token";
 import {
   buildWhirlpoolClient,
+  IGNORE_CACHE,
   ORCA_WHIRLPOOL_PROGRAM_ID,
   PDAUtil,
+  PoolUtil,
   PriceMath,
+  SwapUtils,
+  toTx,
   WhirlpoolContext,
+  WhirlpoolIx,
 } from "@orca-so/whirlpools-sdk";
 import { Percentage } from "@orca-so/common-sdk";
 import { Decimal } from "decimal.js";
@@ -37,6 +45,9 @@ describe("Vault Tests", () => {
   let admin = Keypair.generate();
   let vaultPda: PublicKey;
   let vaultBump: number;
+  let observationPda: PublicKey;
+  let rewardStatePda: PublicKey;
+  let deadSharesPda: PublicKey;
   let whirlpoolConfig = new PublicKey(
     "2LecshUwdy9xi7meFgHtFJQNSKk4KdTrcpvaB56dP2NQ"
   );
@@ -45,6 +56,19 @@ describe("Vault Tests", () => {
     "So11111111111111111111111111111111111111112" // WSOL mint
   );
 
+  // Pyth price accounts loaded from fixtures/accounts/pyth
+  const solPriceFeed = new PublicKey(
+    "H6ARHf6YXhGYeQfUzQNGk6rDNnLBQKrenN712K4AQJEG"
+  );
+  const usdcPriceFeed = new PublicKey(
+    "Gnt27xtC473ZT2Mw5u8wZ68Z3gULkSTb5DuxJy7eJotD"
+  );
+  // The fixtures carry a fixed publish time, so allow a generous max age
+  const maxPriceAge = new BN(10 * 365 * 24 * 60 * 60);
+  const maxConfidenceBps = 100;
+  const maxTickDeviation = 500;
+  const twapWindow = new BN(150);
+
   let whirlpool: PublicKey;
   let tokenUSDCMint: PublicKey;
   let tokenAMint: PublicKey;
@@ -52,11 +76,22 @@ describe("Vault Tests", () => {
   let tokenAVault = Keypair.generate();
   let tokenBVault = Keypair.generate();
   let shareMint = Keypair.generate();
+
+  const rewardCheckpointPda = (owner: PublicKey) =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("reward_checkpoint"), vaultPda.toBuffer(), owner.toBuffer()],
+      program.programId
+    )[0];
   let adminSolAccount: PublicKey;
   let adminUSDCAccount: PublicKey;
   let adminShareAccount: PublicKey;
   let adminTokenAAccount: PublicKey;
   let adminTokenBAccount: PublicKey;
+  let tokenAPriceFeed: PublicKey;
+  let tokenBPriceFeed: PublicKey;
+  let treasury = Keypair.generate();
+  let treasuryTokenAAccount: PublicKey;
+  let treasuryTokenBAccount: PublicKey;
   // Add other necessary variables
 
   before(async () => {
@@ -129,7 +164,9 @@ describe("Vault Tests", () => {
 
     // Create Whirlpool
     const tickSpacing = 64; // Standard tick spacing
-    const initialPrice = 10; // Initial price of 1 TokenA per TokenB
+    // Start the pool at the fixture oracle price (1 SOL = 150 USDC) so the
+    // price deviation guard lets deposits through
+    let initialPrice: number;
     let tokenADecimal: number;
     let tokenBDecimal: number;
 
@@ -138,17 +175,37 @@ describe("Vault Tests", () => {
       tokenBMint = tokenUSDCMint;
       adminTokenAAccount = adminSolAccount;
       adminTokenBAccount = adminUSDCAccount;
+      tokenAPriceFeed = solPriceFeed;
+      tokenBPriceFeed = usdcPriceFeed;
       tokenADecimal = 9;
       tokenBDecimal = 6;
+      initialPrice = 150;
     } else {
       tokenAMint = tokenUSDCMint;
       tokenBMint = tokenSolMint;
       adminTokenAAccount = adminUSDCAccount;
       adminTokenBAccount = adminSolAccount;
+      tokenAPriceFeed = usdcPriceFeed;
+      tokenBPriceFeed = solPriceFeed;
       tokenADecimal = 6;
       tokenBDecimal = 9;
+      initialPrice = 1 / 150;
     }
 
+    // Treasury accounts receiving the withdrawal fee
+    treasuryTokenAAccount = await createAccount(
+      provider.connection,
+      admin,
+      tokenAMint,
+      treasury.publicKey
+    );
+    treasuryTokenBAccount = await createAccount(
+      provider.connection,
+      admin,
+      tokenBMint,
+      treasury.publicKey
+    );
+
     // Get the Whirlpool PDA
     whirlpool = PDAUtil.getWhirlpool(
       ORCA_WHIRLPOOL_PROGRAM_ID,
@@ -184,6 +241,18 @@ describe("Vault Tests", () => {
       [Buffer.from("vault"), whirlpool.toBuffer()],
       program.programId
     );
+    [observationPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("observation"), whirlpool.toBuffer()],
+      program.programId
+    );
+    [rewardStatePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("reward_state"), whirlpool.toBuffer()],
+      program.programId
+    );
+    [deadSharesPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("dead_shares"), vaultPda.toBuffer()],
+      program.programId
+    );
 
     // Initialize other test setup
   });
@@ -191,16 +260,31 @@ describe("Vault Tests", () => {
   it("Initialize vault", async () => {
     try {
       await program.methods
-        .initializeVault(new BN(100), new BN(1000), -1000, 1000)
+        .initializeVault(
+          -1000,
+          1000,
+          { pyth: {} },
+          maxPriceAge,
+          maxConfidenceBps,
+          maxTickDeviation,
+          twapWindow
+        )
         .accounts({
           admin: admin.publicKey,
           vault: vaultPda,
+          observationState: observationPda,
+          rewardState: rewardStatePda,
           whirlpool: whirlpool,
           tokenAMint: tokenAMint,
           tokenBMint: tokenBMint,
           tokenAVault: tokenAVault.publicKey,
           tokenBVault: tokenBVault.publicKey,
           shareMint: shareMint.publicKey,
+          deadShares: deadSharesPda,
+          treasuryTokenA: treasuryTokenAAccount,
+          treasuryTokenB: treasuryTokenBAccount,
+          tokenAPriceFeed: tokenAPriceFeed,
+          tokenBPriceFeed: tokenBPriceFeed,
           tokenProgram: TOKEN_PROGRAM_ID,
           systemProgram: SystemProgram.programId,
           rent: SYSVAR_RENT_PUBKEY,
@@ -216,8 +300,25 @@ describe("Vault Tests", () => {
       expect(vaultAccount.tokenAVault).to.eql(tokenAVault.publicKey);
       expect(vaultAccount.tokenBVault).to.eql(tokenBVault.publicKey);
       expect(vaultAccount.shareMint).to.eql(shareMint.publicKey);
-      expect(vaultAccount.tokenAPrice.toString()).to.eql("100");
-      expect(vaultAccount.tokenBPrice.toString()).to.eql("1000");
+      expect(vaultAccount.pricingMode).to.eql({ pyth: {} });
+      expect(vaultAccount.tokenAPriceFeed).to.eql(tokenAPriceFeed);
+      expect(vaultAccount.tokenBPriceFeed).to.eql(tokenBPriceFeed);
+      expect(vaultAccount.maxPriceAge.toString()).to.eql(maxPriceAge.toString());
+      expect(vaultAccount.maxConfidenceBps).to.eql(maxConfidenceBps);
+      expect(vaultAccount.maxTickDeviation).to.eql(maxTickDeviation);
+      expect(vaultAccount.twapWindow.toString()).to.eql(twapWindow.toString());
+      expect(vaultAccount.maxPriceDeviationBps).to.eql(100);
+      expect(vaultAccount.treasuryTokenA).to.eql(treasuryTokenAAccount);
+      expect(vaultAccount.treasuryTokenB).to.eql(treasuryTokenBAccount);
+      expect(vaultAccount.depositFeeBps).to.eql(0);
+      expect(vaultAccount.withdrawFeeBps).to.eql(0);
+
+      const observationAccount = await program.account.observationState.fetch(
+        observationPda
+      );
+      expect(observationAccount.vault).to.eql(vaultPda);
+      expect(observationAccount.count).to.eql(1);
+      expect(observationAccount.head).to.eql(1);
       expect(vaultAccount.tokenADecimal).to.eql(9);
       expect(vaultAccount.tokenBDecimal).to.eql(6);
       expect(vaultAccount.lowerTick).to.eql(-1000);
@@ -228,31 +329,43 @@ describe("Vault Tests", () => {
     }
   });
 
-  // it("Update prices", async () => {
-  //   const newPriceA = new BN(2000); // Example price
-  //   const newPriceB = new BN(200); // Example price
-  //   try {
-  //     await program.methods
-  //       .updatePrices(newPriceA, newPriceB)
-  //       .accounts({
-  //         vault: vaultPda,
-  //         admin: wallet.publicKey,
-  //         whirlpool: whirlpool,
-  //       })
-  //       .rpc();
+  it("Records an observation", async () => {
+    // Records are spaced at least 1/31 of the TWAP window apart
+    const minInterval = Math.ceil(twapWindow.toNumber() / 31);
+    const { observations } = await program.account.observationState.fetch(
+      observationPda
+    );
+    while (
+      (await provider.connection.getSlot()) <
+      observations[0].slot.toNumber() + minInterval
+    ) {
+      await new Promise((resolve) => setTimeout(resolve, 400));
+    }
 
-  //     const vaultAccount = await program.account.vault.fetch(vaultPda);
-  //     expect(vaultAccount.tokenAPrice.toString()).to.equal(
-  //       newPriceA.toString()
-  //     );
-  //     expect(vaultAccount.tokenBPrice.toString()).to.equal(
-  //       newPriceB.toString()
-  //     );
-  //   } catch (error) {
-  //     console.error("Error:", error);
-  //     throw error;
-  //   }
-  // });
+    await program.methods
+      .recordObservation()
+      .accounts({
+        whirlpool: whirlpool,
+        vault: vaultPda,
+        observationState: observationPda,
+      })
+      .rpc();
+
+    const observationAccount = await program.account.observationState.fetch(
+      observationPda
+    );
+    expect(observationAccount.count).to.eql(2);
+    const [first, second] = observationAccount.observations;
+    expect(second.slot.toNumber()).to.be.at.least(
+      first.slot.toNumber() + minInterval
+    );
+    // The first tick holds until the second record
+    expect(second.tickCumulative.toString()).to.eql(
+      new BN(first.tickCurrentIndex)
+        .mul(second.slot.sub(first.slot))
+        .toString()
+    );
+  });
 
   // it("Update ticks", async () => {
   //   const newLowerTick = -2000;
@@ -277,6 +390,256 @@ describe("Vault Tests", () => {
   //   }
   // });
 
+  it("Rejects deposits priced from a stale feed", async () => {
+    const updatePriceConfig = (maxAge: BN) =>
+      program.methods
+        .updatePriceConfig(maxAge, maxConfidenceBps, maxTickDeviation, 100)
+        .accounts({
+          admin: admin.publicKey,
+          whirlpool: whirlpool,
+          vault: vaultPda,
+        })
+        .signers([admin])
+        .rpc();
+
+    adminShareAccount = await createAccount(
+      provider.connection,
+      admin,
+      shareMint.publicKey,
+      admin.publicKey
+    );
+
+    await updatePriceConfig(new BN(0));
+    const vaultAccount = await program.account.vault.fetch(vaultPda);
+    expect(vaultAccount.maxPriceAge.toString()).to.eql("0");
+
+    try {
+      await program.methods
+        .deposit(new BN(1000), new BN(1000), new BN(0), null)
+        .accounts({
+          vault: vaultPda,
+          user: admin.publicKey,
+          whirlpool: whirlpool,
+          position: null,
+          userTokenA: adminTokenAAccount,
+          userTokenB: adminTokenBAccount,
+          tokenAVault: tokenAVault.publicKey,
+          tokenBVault: tokenBVault.publicKey,
+          shareMint: shareMint.publicKey,
+          deadShares: deadSharesPda,
+          userShare: adminShareAccount,
+          feeRecipient: null,
+          tokenAPriceFeed: tokenAPriceFeed,
+          tokenBPriceFeed: tokenBPriceFeed,
+          observationState: observationPda,
+          rewardState: rewardStatePda,
+          userRewardCheckpoint: rewardCheckpointPda(admin.publicKey),
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([admin])
+        .rpc();
+      expect.fail("Expected deposit to fail");
+    } catch (err) {
+      expect(err.toString()).to.include("StalePrice");
+    } finally {
+      await updatePriceConfig(maxPriceAge);
+    }
+  });
+
+  it("Caps management and performance fees", async () => {
+    const updateFeeConfig = (managementFeeBps: number, performanceFeeBps: number) =>
+      program.methods
+        .updateFeeConfig(managementFeeBps, performanceFeeBps)
+        .accounts({
+          admin: admin.publicKey,
+          whirlpool: whirlpool,
+          vault: vaultPda,
+          feeRecipient: adminShareAccount,
+        })
+        .signers([admin])
+        .rpc();
+
+    try {
+      await updateFeeConfig(501, 0);
+      expect.fail("Expected fee config update to fail");
+    } catch (err) {
+      expect(err.toString()).to.include("FeeTooHigh");
+    }
+
+    await updateFeeConfig(0, 0);
+    const vaultAccount = await program.account.vault.fetch(vaultPda);
+    expect(vaultAccount.feeRecipient.toString()).to.eql(
+      adminShareAccount.toString()
+    );
+    expect(vaultAccount.managementFeeBps).to.eql(0);
+    expect(vaultAccount.performanceFeeBps).to.eql(0);
+  });
+
+  it("Caps deposit and withdrawal fees", async () => {
+    const updateFeeSchedule = (depositFeeBps: number, withdrawFeeBps: number) =>
+      program.methods
+        .updateFeeSchedule(depositFeeBps, withdrawFeeBps)
+        .accounts({
+          admin: admin.publicKey,
+          whirlpool: whirlpool,
+          vault: vaultPda,
+        })
+        .signers([admin])
+        .rpc();
+
+    try {
+      await updateFeeSchedule(0, 101);
+      expect.fail("Expected fee schedule update to fail");
+    } catch (err) {
+      expect(err.toString()).to.include("FeeTooHigh");
+    }
+
+    await updateFeeSchedule(0, 0);
+    const vaultAccount = await program.account.vault.fetch(vaultPda);
+    expect(vaultAccount.depositFeeBps).to.eql(0);
+    expect(vaultAccount.withdrawFeeBps).to.eql(0);
+  });
+
+  it("Switches the deposit mode", async () => {
+    const updateDepositMode = (depositMode: object) =>
+      program.methods
+        .updateDepositMode(depositMode as any)
+        .accounts({
+          admin: admin.publicKey,
+          whirlpool: whirlpool,
+          vault: vaultPda,
+        })
+        .signers([admin])
+        .rpc();
+
+    expect((await program.account.vault.fetch(vaultPda)).depositMode).to.eql({
+      valued: {},
+    });
+
+    await updateDepositMode({ proportional: {} });
+    expect((await program.account.vault.fetch(vaultPda)).depositMode).to.eql({
+      proportional: {},
+    });
+
+    await updateDepositMode({ valued: {} });
+  });
+
+  it("Separates the owner from the other roles", async () => {
+    const keeper = Keypair.generate();
+    const updateRoles = (owner: Keypair, roleHolder: PublicKey) =>
+      program.methods
+        .updateRoles(roleHolder, roleHolder, roleHolder)
+        .accounts({
+          admin: owner.publicKey,
+          whirlpool: whirlpool,
+          vault: vaultPda,
+        })
+        .signers([owner])
+        .rpc();
+    const setPaused = (authority: Keypair, paused: boolean) =>
+      program.methods
+        .setPaused(paused)
+        .accounts({
+          authority: authority.publicKey,
+          whirlpool: whirlpool,
+          vault: vaultPda,
+        })
+        .signers([authority])
+        .rpc();
+
+    await updateRoles(admin, keeper.publicKey);
+    let vaultAccount = await program.account.vault.fetch(vaultPda);
+    expect(vaultAccount.admin.toBase58()).to.eql(admin.publicKey.toBase58());
+    expect(vaultAccount.operator.toBase58()).to.eql(keeper.publicKey.toBase58());
+    expect(vaultAccount.feeManager.toBase58()).to.eql(keeper.publicKey.toBase58());
+    expect(vaultAccount.pauser.toBase58()).to.eql(keeper.publicKey.toBase58());
+
+    // The keeper holds every role but ownership, so it cannot hand roles out
+    try {
+      await updateRoles(keeper, keeper.publicKey);
+      expect.fail("Expected role update to fail");
+    } catch (err) {
+      expect(err.toString()).to.include("ConstraintRaw");
+    }
+
+    // Fees stay with the owner
+    try {
+      await program.methods
+        .updateFeeSchedule(0, 0)
+        .accounts({
+          admin: keeper.publicKey,
+          whirlpool: whirlpool,
+          vault: vaultPda,
+        })
+        .signers([keeper])
+        .rpc();
+      expect.fail("Expected fee update to fail");
+    } catch (err) {
+      expect(err.toString()).to.include("ConstraintRaw");
+    }
+
+    await setPaused(keeper, true);
+    vaultAccount = await program.account.vault.fetch(vaultPda);
+    expect(vaultAccount.paused).to.eql(true);
+
+    await setPaused(admin, false);
+    await updateRoles(admin, admin.publicKey);
+    vaultAccount = await program.account.vault.fetch(vaultPda);
+    expect(vaultAccount.paused).to.eql(false);
+    expect(vaultAccount.operator.toBase58()).to.eql(admin.publicKey.toBase58());
+  });
+
+  it("Transfers ownership in two steps", async () => {
+    const newAdmin = Keypair.generate();
+    const proposeAdmin = (owner: Keypair, pendingAdmin: PublicKey) =>
+      program.methods
+        .proposeAdmin(pendingAdmin)
+        .accounts({
+          admin: owner.publicKey,
+          whirlpool: whirlpool,
+          vault: vaultPda,
+        })
+        .signers([owner])
+        .rpc();
+    const acceptAdmin = (pendingAdmin: Keypair) =>
+      program.methods
+        .acceptAdmin()
+        .accounts({
+          pendingAdmin: pendingAdmin.publicKey,
+          whirlpool: whirlpool,
+          vault: vaultPda,
+        })
+        .signers([pendingAdmin])
+        .rpc();
+
+    await proposeAdmin(admin, newAdmin.publicKey);
+    let vaultAccount = await program.account.vault.fetch(vaultPda);
+    expect(vaultAccount.admin.toBase58()).to.eql(admin.publicKey.toBase58());
+    expect(vaultAccount.pendingAdmin.toBase58()).to.eql(
+      newAdmin.publicKey.toBase58()
+    );
+
+    // Only the proposed key can accept
+    try {
+      await acceptAdmin(Keypair.generate());
+      expect.fail("Expected accept to fail");
+    } catch (err) {
+      expect(err.toString()).to.include("ConstraintRaw");
+    }
+
+    await acceptAdmin(newAdmin);
+    vaultAccount = await program.account.vault.fetch(vaultPda);
+    expect(vaultAccount.admin.toBase58()).to.eql(newAdmin.publicKey.toBase58());
+    expect(vaultAccount.pendingAdmin.toBase58()).to.eql(
+      PublicKey.default.toBase58()
+    );
+
+    // Hand the vault back for the remaining tests
+    await proposeAdmin(newAdmin, admin.publicKey);
+    await acceptAdmin(admin);
+  });
+
   it("Deposits into vault", async () => {
     const depositSolAmount = 1 * LAMPORTS_PER_SOL; // 1 SOL
     const depositUSDCAmount = 10000000; // 10 USDC
@@ -290,16 +653,14 @@ describe("Vault Tests", () => {
       depositTokenBAmount = depositSolAmount;
     }
 
-    try {
-      adminShareAccount = await createAccount(
-        provider.connection,
-        admin,
-        shareMint.publicKey,
-        admin.publicKey
-      );
-
-      await program.methods
-        .deposit(new BN(depositTokenAAmount), new BN(depositTokenBAmount))
+    const deposit = (minSharesOut: BN, deadline: BN | null) =>
+      program.methods
+        .deposit(
+          new BN(depositTokenAAmount),
+          new BN(depositTokenBAmount),
+          minSharesOut,
+          deadline
+        )
         .accounts({
           vault: vaultPda,
           user: admin.publicKey,
@@ -310,12 +671,40 @@ describe("Vault Tests", () => {
           tokenAVault: tokenAVault.publicKey,
           tokenBVault: tokenBVault.publicKey,
           shareMint: shareMint.publicKey,
+          deadShares: deadSharesPda,
           userShare: adminShareAccount,
+          feeRecipient: null,
+          tokenAPriceFeed: tokenAPriceFeed,
+          tokenBPriceFeed: tokenBPriceFeed,
+          observationState: observationPda,
+          rewardState: rewardStatePda,
+          userRewardCheckpoint: rewardCheckpointPda(admin.publicKey),
           tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
         })
         .signers([admin])
         .rpc();
 
+    // 1 SOL at $150 plus 10 USDC at $1 mints 160 shares, less the locked dead shares
+    const expectedShares = new BN(159999000);
+    try {
+      await deposit(expectedShares.addn(1), null);
+      expect.fail("Expected deposit to fail");
+    } catch (err) {
+      expect(err.toString()).to.include("SlippageExceeded");
+    }
+
+    const now = Math.floor(Date.now() / 1000);
+    try {
+      await deposit(expectedShares, new BN(now - 3600));
+      expect.fail("Expected deposit to fail");
+    } catch (err) {
+      expect(err.toString()).to.include("DeadlineExceeded");
+    }
+
+    try {
+      await deposit(expectedShares, new BN(now + 3600));
+
       // Verify the deposit
       const tokenAVaultBalance =
         await provider.connection.getTokenAccountBalance(tokenAVault.publicKey);
@@ -328,16 +717,84 @@ describe("Vault Tests", () => {
       expect(tokenBVaultBalance.value.amount).to.equal(
         depositTokenBAmount.toString()
       );
+
+      const shareBalance = await provider.connection.getTokenAccountBalance(
+        adminShareAccount
+      );
+      expect(shareBalance.value.amount).to.equal("159999000");
+
+      const checkpoint = await program.account.userRewardCheckpoint.fetch(
+        rewardCheckpointPda(admin.publicKey)
+      );
+      expect(checkpoint.shares.toString()).to.equal("159999000");
     } catch (err) {
       console.error("Error:", err);
       throw err;
     }
   });
 
-  it("Withdraws from vault", async () => {
+  it("Keeps shares non-transferable", async () => {
+    // A share balance can only be recorded on one reward checkpoint
+    const shareAccount = await getAccount(provider.connection, adminShareAccount);
+    expect(shareAccount.isFrozen).to.be.true;
+
+    const otherShareAccount = await createAccount(
+      provider.connection,
+      admin,
+      shareMint.publicKey,
+      Keypair.generate().publicKey
+    );
+    try {
+      await transfer(
+        provider.connection,
+        admin,
+        adminShareAccount,
+        otherShareAccount,
+        admin,
+        1
+      );
+      expect.fail("Expected transfer to fail");
+    } catch (err) {
+      // TokenError::AccountFrozen
+      expect(err.toString()).to.include("0x11");
+    }
+  });
+
+  it("Withdraws from vault while the price feed is stale", async () => {
+    const updatePriceConfig = (maxAge: BN) =>
+      program.methods
+        .updatePriceConfig(maxAge, maxConfidenceBps, maxTickDeviation, 100)
+        .accounts({
+          admin: admin.publicKey,
+          whirlpool: whirlpool,
+          vault: vaultPda,
+        })
+        .signers([admin])
+        .rpc();
+
+    // Redemptions are pro rata and need no price
+    await updatePriceConfig(new BN(0));
     try {
       const shareTokenBalanceBefore =
         await provider.connection.getTokenAccountBalance(adminShareAccount);
+      const tokenAVaultBalanceBefore =
+        await provider.connection.getTokenAccountBalance(tokenAVault.publicKey);
+      const tokenBVaultBalanceBefore =
+        await provider.connection.getTokenAccountBalance(tokenBVault.publicKey);
+      const shareSupply = await provider.connection.getTokenSupply(
+        shareMint.publicKey
+      );
+      // The dead shares keep their slice of the vault
+      const remaining = (balance: string) => {
+        const amount = new BN(balance);
+        return amount
+          .sub(
+            amount
+              .mul(new BN(shareTokenBalanceBefore.value.amount))
+              .div(new BN(shareSupply.value.amount))
+          )
+          .toString();
+      };
 
       await program.methods
         .withdraw(
@@ -348,15 +805,30 @@ describe("Vault Tests", () => {
         .accounts({
           vault: vaultPda,
           user: admin.publicKey,
+          whirlpoolProgram: ORCA_WHIRLPOOL_PROGRAM_ID,
           whirlpool: whirlpool,
           position: null,
+          positionTokenAccount: null,
+          poolTokenVaultA: null,
+          poolTokenVaultB: null,
+          tickArrayLower: null,
+          tickArrayUpper: null,
           userTokenA: adminTokenAAccount,
           userTokenB: adminTokenBAccount,
           tokenAVault: tokenAVault.publicKey,
           tokenBVault: tokenBVault.publicKey,
           shareMint: shareMint.publicKey,
           userShare: adminShareAccount,
+          feeRecipient: null,
+          treasuryTokenA: treasuryTokenAAccount,
+          treasuryTokenB: treasuryTokenBAccount,
+          tokenAPriceFeed: tokenAPriceFeed,
+          tokenBPriceFeed: tokenBPriceFeed,
+          observationState: observationPda,
+          rewardState: rewardStatePda,
+          userRewardCheckpoint: rewardCheckpointPda(admin.publicKey),
           tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
         })
         .signers([admin])
         .rpc();
@@ -368,34 +840,176 @@ describe("Vault Tests", () => {
       const shareTokenBalanceAfter =
         await provider.connection.getTokenAccountBalance(adminShareAccount);
 
-      expect(tokenAVaultBalance.value.amount).to.equal("0");
-      expect(tokenBVaultBalance.value.amount).to.equal("0");
+      expect(tokenAVaultBalance.value.amount).to.equal(
+        remaining(tokenAVaultBalanceBefore.value.amount)
+      );
+      expect(tokenBVaultBalance.value.amount).to.equal(
+        remaining(tokenBVaultBalanceBefore.value.amount)
+      );
       expect(shareTokenBalanceAfter.value.amount).to.equal("0");
     } catch (err) {
       console.error("Error:", err);
       throw err;
+    } finally {
+      await updatePriceConfig(maxPriceAge);
     }
   });
 
-  // it("Fails to withdraw more than available balance", async () => {
-  //   const withdrawAmount = 2 * LAMPORTS_PER_SOL; // 2 SOL (more than deposited)
+  it("Timelocks changes to prices, ticks, fees and policies", async () => {
+    const pendingChangePda = async () => {
+      const { nextChangeId } = await program.account.vault.fetch(vaultPda);
+      return PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("pending_change"),
+          vaultPda.toBuffer(),
+          nextChangeId.toArrayLike(Buffer, "le", 8),
+        ],
+        program.programId
+      )[0];
+    };
+    const scheduleChange = async (change: object) => {
+      const pendingChange = await pendingChangePda();
+      await program.methods
+        .scheduleChange(change as any)
+        .accounts({
+          admin: admin.publicKey,
+          whirlpool: whirlpool,
+          vault: vaultPda,
+          pendingChange,
+          feeRecipient: null,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([admin])
+        .rpc();
+      return pendingChange;
+    };
+    const changeAccounts = (pendingChange: PublicKey) => ({
+      admin: admin.publicKey,
+      whirlpool: whirlpool,
+      vault: vaultPda,
+      pendingChange,
+    });
+    const executeChange = (pendingChange: PublicKey) =>
+      program.methods
+        .executeChange()
+        .accounts(changeAccounts(pendingChange))
+        .signers([admin])
+        .rpc();
 
-  //   try {
-  //     await program.methods
-  //       .withdraw(new anchor.BN(withdrawAmount))
-  //       .accounts({
-  //         vault: vaultPDA,
-  //         authority: provider.wallet.publicKey,
-  //         systemProgram: SystemProgram.programId,
-  //       })
-  //       .rpc();
+    // Without a delay the change lands right away
+    await executeChange(
+      await scheduleChange({ timelockDelay: { timelockDelay: new BN(2) } })
+    );
+    let vaultAccount = await program.account.vault.fetch(vaultPda);
+    expect(vaultAccount.timelockDelay.toString()).to.eql("2");
 
-  //     // Should not reach here
-  //     expect.fail("Expected withdrawal to fail");
-  //   } catch (err) {
-  //     expect(err.toString()).to.include("insufficient funds");
-  //   }
-  // });
+    try {
+      await program.methods
+        .updateFeeSchedule(0, 0)
+        .accounts({
+          admin: admin.publicKey,
+          whirlpool: whirlpool,
+          vault: vaultPda,
+        })
+        .signers([admin])
+        .rpc();
+      expect.fail("Expected direct update to fail");
+    } catch (err) {
+      expect(err.toString()).to.include("TimelockActive");
+    }
+    try {
+      await program.methods
+        .updateKeeperConfig(50, new BN(10))
+        .accounts({
+          admin: admin.publicKey,
+          whirlpool: whirlpool,
+          vault: vaultPda,
+        })
+        .signers([admin])
+        .rpc();
+      expect.fail("Expected direct update to fail");
+    } catch (err) {
+      expect(err.toString()).to.include("TimelockActive");
+    }
+
+    try {
+      await program.methods
+        .updateRoles(admin.publicKey, admin.publicKey, admin.publicKey)
+        .accounts({
+          admin: admin.publicKey,
+          whirlpool: whirlpool,
+          vault: vaultPda,
+        })
+        .signers([admin])
+        .rpc();
+      expect.fail("Expected direct update to fail");
+    } catch (err) {
+      expect(err.toString()).to.include("TimelockActive");
+    }
+
+    // The reference tick only ever snaps to the live tick, so it stays refreshable
+    await program.methods
+      .updateReferenceTick()
+      .accounts({
+        admin: admin.publicKey,
+        whirlpool: whirlpool,
+        vault: vaultPda,
+      })
+      .signers([admin])
+      .rpc();
+
+    const feeChange = await scheduleChange({
+      feeSchedule: { depositFeeBps: 10, withdrawFeeBps: 10 },
+    });
+    try {
+      await executeChange(feeChange);
+      expect.fail("Expected execute to fail");
+    } catch (err) {
+      expect(err.toString()).to.include("ChangeNotReady");
+    }
+    await program.methods
+      .cancelChange()
+      .accounts(changeAccounts(feeChange))
+      .signers([admin])
+      .rpc();
+    expect(await provider.connection.getAccountInfo(feeChange)).to.be.null;
+
+    // Keeper settings and lifting the timelock wait out the delay as well
+    const keeperChange = await scheduleChange({
+      keeperConfig: { compoundBountyBps: 50, minCompoundInterval: new BN(10) },
+    });
+    const delayChange = await scheduleChange({
+      timelockDelay: { timelockDelay: new BN(0) },
+    });
+    await new Promise((resolve) => setTimeout(resolve, 3000));
+    await executeChange(keeperChange);
+    await executeChange(delayChange);
+    vaultAccount = await program.account.vault.fetch(vaultPda);
+    expect(vaultAccount.compoundBountyBps).to.eql(50);
+    expect(vaultAccount.minCompoundInterval.toString()).to.eql("10");
+    expect(vaultAccount.timelockDelay.toString()).to.eql("0");
+    expect(vaultAccount.depositFeeBps).to.eql(0);
+  });
+
+  // it("Fails to withdraw more than available balance", async () => {
+  //   const withdrawAmount = 2 * LAMPORTS_PER_SOL; // 2 SOL (more than deposited)
+
+  //   try {
+  //     await program.methods
+  //       .withdraw(new anchor.BN(withdrawAmount))
+  //       .accounts({
+  //         vault: vaultPDA,
+  //         authority: provider.wallet.publicKey,
+  //         systemProgram: SystemProgram.programId,
+  //       })
+  //       .rpc();
+
+  //     // Should not reach here
+  //     expect.fail("Expected withdrawal to fail");
+  //   } catch (err) {
+  //     expect(err.toString()).to.include("insufficient funds");
+  //   }
+  // });
 
   // it("Fails to withdraw with wrong authority", async () => {
   //   const wrongKeypair = anchor.web3.Keypair.generate();
@@ -450,3 +1064,992 @@ describe("Vault Tests", () => {
   //   }
   // });
 });
+
+describe("Switchboard-priced vault", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Vault as Program<Vault>;
+  const wallet = provider.wallet;
+
+  let user = Keypair.generate();
+  let whirlpoolConfig = new PublicKey(
+    "2LecshUwdy9xi7meFgHtFJQNSKk4KdTrcpvaB56dP2NQ"
+  );
+  let tokenSolMint: PublicKey = new PublicKey(
+    "So11111111111111111111111111111111111111112"
+  );
+
+  // Hand-built Switchboard On-Demand pull feeds from fixtures/accounts/switchboard
+  const solPriceFeed = new PublicKey(
+    "PtSA1pFS9hD6Kp5jqmpK6k81EtjpBL12W1fsVSgpqTi"
+  );
+  const usdtPriceFeed = new PublicKey(
+    "3vwpFK7wZqih4PRLctkUQY9nuxPNcktkgw3VDjvNZASn"
+  );
+  const maxPriceAge = new BN(10 * 365 * 24 * 60 * 60);
+
+  let whirlpool: PublicKey;
+  let vaultPda: PublicKey;
+  let observationPda: PublicKey;
+  let rewardStatePda: PublicKey;
+  let deadSharesPda: PublicKey;
+  let tokenAMint: PublicKey;
+  let tokenBMint: PublicKey;
+  let userTokenAAccount: PublicKey;
+  let userTokenBAccount: PublicKey;
+  let userShareAccount: PublicKey;
+  let tokenAPriceFeed: PublicKey;
+  let tokenBPriceFeed: PublicKey;
+  let depositTokenAAmount: number;
+  let depositTokenBAmount: number;
+  let tokenAVault = Keypair.generate();
+  let tokenBVault = Keypair.generate();
+  let shareMint = Keypair.generate();
+
+  const rewardCheckpointPda = (owner: PublicKey) =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("reward_checkpoint"), vaultPda.toBuffer(), owner.toBuffer()],
+      program.programId
+    )[0];
+
+  before(async () => {
+    const { blockhash, lastValidBlockHeight } =
+      await provider.connection.getLatestBlockhash();
+    const signature = await provider.connection.requestAirdrop(
+      user.publicKey,
+      100 * LAMPORTS_PER_SOL
+    );
+    await provider.connection.confirmTransaction(
+      { signature, blockhash, lastValidBlockHeight },
+      "finalized"
+    );
+
+    const userSolAccount = await createAccount(
+      provider.connection,
+      user,
+      tokenSolMint,
+      user.publicKey
+    );
+    const tx = new anchor.web3.Transaction()
+      .add(
+        SystemProgram.transfer({
+          fromPubkey: user.publicKey,
+          toPubkey: userSolAccount,
+          lamports: 10 * LAMPORTS_PER_SOL,
+        })
+      )
+      .add(createSyncNativeInstruction(userSolAccount));
+    await provider.sendAndConfirm(tx, [user]);
+
+    const tokenUSDTMint = await createMint(
+      provider.connection,
+      user,
+      user.publicKey,
+      user.publicKey,
+      6
+    );
+    const userUSDTAccount = await createAccount(
+      provider.connection,
+      user,
+      tokenUSDTMint,
+      user.publicKey
+    );
+    await mintTo(
+      provider.connection,
+      user,
+      tokenUSDTMint,
+      userUSDTAccount,
+      user.publicKey,
+      1000000000
+    );
+
+    let initialPrice: number;
+    let tokenADecimal: number;
+    let tokenBDecimal: number;
+    if (tokenSolMint.toBuffer().compare(tokenUSDTMint.toBuffer()) < 0) {
+      tokenAMint = tokenSolMint;
+      tokenBMint = tokenUSDTMint;
+      userTokenAAccount = userSolAccount;
+      userTokenBAccount = userUSDTAccount;
+      tokenAPriceFeed = solPriceFeed;
+      tokenBPriceFeed = usdtPriceFeed;
+      depositTokenAAmount = 1 * LAMPORTS_PER_SOL;
+      depositTokenBAmount = 10000000;
+      tokenADecimal = 9;
+      tokenBDecimal = 6;
+      initialPrice = 150;
+    } else {
+      tokenAMint = tokenUSDTMint;
+      tokenBMint = tokenSolMint;
+      userTokenAAccount = userUSDTAccount;
+      userTokenBAccount = userSolAccount;
+      tokenAPriceFeed = usdtPriceFeed;
+      tokenBPriceFeed = solPriceFeed;
+      depositTokenAAmount = 10000000;
+      depositTokenBAmount = 1 * LAMPORTS_PER_SOL;
+      tokenADecimal = 6;
+      tokenBDecimal = 9;
+      initialPrice = 1 / 150;
+    }
+
+    const whirlpoolClient = buildWhirlpoolClient(
+      WhirlpoolContext.withProvider(provider, ORCA_WHIRLPOOL_PROGRAM_ID)
+    );
+    const tickSpacing = 64;
+    whirlpool = PDAUtil.getWhirlpool(
+      ORCA_WHIRLPOOL_PROGRAM_ID,
+      whirlpoolConfig,
+      tokenAMint,
+      tokenBMint,
+      tickSpacing
+    ).publicKey;
+    const createPoolTx = await whirlpoolClient.createPool(
+      whirlpoolConfig,
+      tokenAMint,
+      tokenBMint,
+      tickSpacing,
+      PriceMath.priceToInitializableTickIndex(
+        new Decimal(initialPrice),
+        tokenADecimal,
+        tokenBDecimal,
+        tickSpacing
+      ),
+      wallet.publicKey
+    );
+    await createPoolTx.tx.buildAndExecute();
+
+    [vaultPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault"), whirlpool.toBuffer()],
+      program.programId
+    );
+    [observationPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("observation"), whirlpool.toBuffer()],
+      program.programId
+    );
+    [rewardStatePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("reward_state"), whirlpool.toBuffer()],
+      program.programId
+    );
+    [deadSharesPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("dead_shares"), vaultPda.toBuffer()],
+      program.programId
+    );
+  });
+
+  it("Initializes a vault priced by Switchboard feeds", async () => {
+    await program.methods
+      .initializeVault(
+        -1000,
+        1000,
+        { switchboard: {} },
+        maxPriceAge,
+        100,
+        500,
+        new BN(150)
+      )
+      .accounts({
+        admin: user.publicKey,
+        vault: vaultPda,
+        observationState: observationPda,
+        rewardState: rewardStatePda,
+        whirlpool: whirlpool,
+        tokenAMint: tokenAMint,
+        tokenBMint: tokenBMint,
+        tokenAVault: tokenAVault.publicKey,
+        tokenBVault: tokenBVault.publicKey,
+        shareMint: shareMint.publicKey,
+        deadShares: deadSharesPda,
+        treasuryTokenA: userTokenAAccount,
+        treasuryTokenB: userTokenBAccount,
+        tokenAPriceFeed: tokenAPriceFeed,
+        tokenBPriceFeed: tokenBPriceFeed,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        rent: SYSVAR_RENT_PUBKEY,
+      })
+      .signers([user, tokenAVault, tokenBVault, shareMint])
+      .rpc();
+
+    const vaultAccount = await program.account.vault.fetch(vaultPda);
+    expect(vaultAccount.pricingMode).to.eql({ switchboard: {} });
+    expect(vaultAccount.tokenAPriceFeed).to.eql(tokenAPriceFeed);
+    expect(vaultAccount.tokenBPriceFeed).to.eql(tokenBPriceFeed);
+  });
+
+  const deposit = () =>
+    program.methods
+      .deposit(
+        new BN(depositTokenAAmount),
+        new BN(depositTokenBAmount),
+        new BN(0),
+        null
+      )
+      .accounts({
+        vault: vaultPda,
+        user: user.publicKey,
+        whirlpool: whirlpool,
+        position: null,
+        userTokenA: userTokenAAccount,
+        userTokenB: userTokenBAccount,
+        tokenAVault: tokenAVault.publicKey,
+        tokenBVault: tokenBVault.publicKey,
+        shareMint: shareMint.publicKey,
+        deadShares: deadSharesPda,
+        userShare: userShareAccount,
+        feeRecipient: null,
+        tokenAPriceFeed: tokenAPriceFeed,
+        tokenBPriceFeed: tokenBPriceFeed,
+        observationState: observationPda,
+        rewardState: rewardStatePda,
+        userRewardCheckpoint: rewardCheckpointPda(user.publicKey),
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user])
+      .rpc();
+
+  it("Rejects Switchboard prices older than max_price_age", async () => {
+    const updatePriceConfig = (maxAge: BN) =>
+      program.methods
+        .updatePriceConfig(maxAge, 100, 500, 100)
+        .accounts({
+          admin: user.publicKey,
+          whirlpool: whirlpool,
+          vault: vaultPda,
+        })
+        .signers([user])
+        .rpc();
+
+    userShareAccount = await createAccount(
+      provider.connection,
+      user,
+      shareMint.publicKey,
+      user.publicKey
+    );
+
+    // The fixture feeds were last updated long before the validator started
+    await updatePriceConfig(new BN(60));
+    try {
+      await deposit();
+      expect.fail("Expected deposit to fail");
+    } catch (err) {
+      expect(err.toString()).to.include("StalePrice");
+    } finally {
+      await updatePriceConfig(maxPriceAge);
+    }
+  });
+
+  it("Mints shares at Switchboard prices", async () => {
+    await deposit();
+
+    // 1 SOL at $150 plus 10 USDT at $1 mints 160 shares, less the locked dead shares
+    const shareBalance = await provider.connection.getTokenAccountBalance(
+      userShareAccount
+    );
+    expect(shareBalance.value.amount).to.equal("159999000");
+  });
+});
+
+describe("Vault position", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Vault as Program<Vault>;
+  const wallet = provider.wallet;
+  const whirlpoolContext = WhirlpoolContext.withProvider(
+    provider,
+    ORCA_WHIRLPOOL_PROGRAM_ID
+  );
+
+  let admin = Keypair.generate();
+  let treasury = Keypair.generate();
+  let whirlpoolConfig = new PublicKey(
+    "2LecshUwdy9xi7meFgHtFJQNSKk4KdTrcpvaB56dP2NQ"
+  );
+  const tickSpacing = 64;
+  // The pool starts at a price of 1, inside the vault's range
+  const lowerTick = -1024;
+  const upperTick = 1024;
+
+  let whirlpool: PublicKey;
+  let poolTokenVaultA: PublicKey;
+  let poolTokenVaultB: PublicKey;
+  let vaultPda: PublicKey;
+  let observationPda: PublicKey;
+  let rewardStatePda: PublicKey;
+  let deadSharesPda: PublicKey;
+  let tokenAMint: PublicKey;
+  let tokenBMint: PublicKey;
+  let adminTokenAAccount: PublicKey;
+  let adminTokenBAccount: PublicKey;
+  let adminShareAccount: PublicKey;
+  let treasuryTokenAAccount: PublicKey;
+  let treasuryTokenBAccount: PublicKey;
+  let positionMint = Keypair.generate();
+  let positionPda: PublicKey;
+  let positionTokenAccount: PublicKey;
+  // Outside liquidity provider's position on the same pool
+  let lpPosition: PublicKey;
+  let tokenAVault = Keypair.generate();
+  let tokenBVault = Keypair.generate();
+  let shareMint = Keypair.generate();
+
+  const rewardCheckpointPda = (owner: PublicKey) =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("reward_checkpoint"), vaultPda.toBuffer(), owner.toBuffer()],
+      program.programId
+    )[0];
+  const tickArray = (tick: number) =>
+    PDAUtil.getTickArrayFromTickIndex(
+      tick,
+      tickSpacing,
+      whirlpool,
+      ORCA_WHIRLPOOL_PROGRAM_ID
+    ).publicKey;
+  const oracle = () =>
+    PDAUtil.getOracle(ORCA_WHIRLPOOL_PROGRAM_ID, whirlpool).publicKey;
+  // Tick arrays a swap from the current price walks through
+  const swapTickArrays = async (aToB: boolean) => {
+    const pool = await whirlpoolContext.fetcher.getPool(
+      whirlpool,
+      IGNORE_CACHE
+    );
+    return SwapUtils.getTickArrayPublicKeys(
+      pool.tickCurrentIndex,
+      tickSpacing,
+      aToB,
+      ORCA_WHIRLPOOL_PROGRAM_ID,
+      whirlpool
+    );
+  };
+
+  // Trades through the pool until its price reaches targetTick
+  const pushPrice = async (targetTick: number) => {
+    const pool = await whirlpoolContext.fetcher.getPool(
+      whirlpool,
+      IGNORE_CACHE
+    );
+    const aToB = targetTick < pool.tickCurrentIndex;
+    const tickArrays = await swapTickArrays(aToB);
+    await toTx(
+      whirlpoolContext,
+      WhirlpoolIx.swapIx(whirlpoolContext.program, {
+        amount: new BN(1_000_000_000_000),
+        otherAmountThreshold: new BN(0),
+        sqrtPriceLimit: PriceMath.tickIndexToSqrtPriceX64(targetTick),
+        amountSpecifiedIsInput: true,
+        aToB,
+        whirlpool,
+        tokenAuthority: admin.publicKey,
+        tokenOwnerAccountA: adminTokenAAccount,
+        tokenVaultA: poolTokenVaultA,
+        tokenOwnerAccountB: adminTokenBAccount,
+        tokenVaultB: poolTokenVaultB,
+        tickArray0: tickArrays[0],
+        tickArray1: tickArrays[1],
+        tickArray2: tickArrays[2],
+        oracle: oracle(),
+      })
+    )
+      .addSigner(admin)
+      .buildAndExecute();
+  };
+
+  const updateReferenceTick = () =>
+    program.methods
+      .updateReferenceTick()
+      .accounts({
+        admin: admin.publicKey,
+        whirlpool: whirlpool,
+        vault: vaultPda,
+      })
+      .signers([admin])
+      .rpc();
+
+  before(async () => {
+    const { blockhash, lastValidBlockHeight } =
+      await provider.connection.getLatestBlockhash();
+    const signature = await provider.connection.requestAirdrop(
+      admin.publicKey,
+      100 * LAMPORTS_PER_SOL
+    );
+    await provider.connection.confirmTransaction(
+      { signature, blockhash, lastValidBlockHeight },
+      "finalized"
+    );
+
+    const mints = [
+      await createMint(provider.connection, admin, admin.publicKey, null, 6),
+      await createMint(provider.connection, admin, admin.publicKey, null, 6),
+    ].sort((a, b) => a.toBuffer().compare(b.toBuffer()));
+    [tokenAMint, tokenBMint] = mints;
+    adminTokenAAccount = await createAccount(
+      provider.connection,
+      admin,
+      tokenAMint,
+      admin.publicKey
+    );
+    adminTokenBAccount = await createAccount(
+      provider.connection,
+      admin,
+      tokenBMint,
+      admin.publicKey
+    );
+    treasuryTokenAAccount = await createAccount(
+      provider.connection,
+      admin,
+      tokenAMint,
+      treasury.publicKey
+    );
+    treasuryTokenBAccount = await createAccount(
+      provider.connection,
+      admin,
+      tokenBMint,
+      treasury.publicKey
+    );
+    await mintTo(
+      provider.connection,
+      admin,
+      tokenAMint,
+      adminTokenAAccount,
+      admin,
+      10_000_000_000_000
+    );
+    await mintTo(
+      provider.connection,
+      admin,
+      tokenBMint,
+      adminTokenBAccount,
+      admin,
+      10_000_000_000_000
+    );
+
+    whirlpool = PDAUtil.getWhirlpool(
+      ORCA_WHIRLPOOL_PROGRAM_ID,
+      whirlpoolConfig,
+      tokenAMint,
+      tokenBMint,
+      tickSpacing
+    ).publicKey;
+    const whirlpoolClient = buildWhirlpoolClient(whirlpoolContext);
+    const createPoolTx = await whirlpoolClient.createPool(
+      whirlpoolConfig,
+      tokenAMint,
+      tokenBMint,
+      tickSpacing,
+      0,
+      wallet.publicKey
+    );
+    await createPoolTx.tx.buildAndExecute();
+
+    // Tick arrays on both sides of the starting price, for positions and swaps
+    const pool = await whirlpoolClient.getPool(whirlpool, IGNORE_CACHE);
+    const initTickArrays = await pool.initTickArrayForTicks([
+      -11264, -5632, 0, 5632, 11264,
+    ]);
+    await initTickArrays?.buildAndExecute();
+    poolTokenVaultA = pool.getData().tokenVaultA;
+    poolTokenVaultB = pool.getData().tokenVaultB;
+
+    // Outside liquidity across the whole initialised range, so the vault's own
+    // swaps trade against a pool deeper than its position
+    const lpPositionMint = Keypair.generate();
+    const lpPositionPda = PDAUtil.getPosition(
+      ORCA_WHIRLPOOL_PROGRAM_ID,
+      lpPositionMint.publicKey
+    );
+    lpPosition = lpPositionPda.publicKey;
+    const lpPositionTokenAccount = await getAssociatedTokenAddress(
+      lpPositionMint.publicKey,
+      admin.publicKey
+    );
+    await toTx(
+      whirlpoolContext,
+      WhirlpoolIx.openPositionIx(whirlpoolContext.program, {
+        funder: admin.publicKey,
+        owner: admin.publicKey,
+        positionPda: lpPositionPda,
+        positionMintAddress: lpPositionMint.publicKey,
+        positionTokenAccount: lpPositionTokenAccount,
+        whirlpool,
+        tickLowerIndex: -11264,
+        tickUpperIndex: 11264,
+      })
+    )
+      .addInstruction(
+        WhirlpoolIx.increaseLiquidityIx(whirlpoolContext.program, {
+          liquidityAmount: new BN(1_000_000_000_000),
+          tokenMaxA: new BN(1_000_000_000_000),
+          tokenMaxB: new BN(1_000_000_000_000),
+          whirlpool,
+          positionAuthority: admin.publicKey,
+          position: lpPositionPda.publicKey,
+          positionTokenAccount: lpPositionTokenAccount,
+          tokenOwnerAccountA: adminTokenAAccount,
+          tokenOwnerAccountB: adminTokenBAccount,
+          tokenVaultA: poolTokenVaultA,
+          tokenVaultB: poolTokenVaultB,
+          tickArrayLower: tickArray(-11264),
+          tickArrayUpper: tickArray(11264),
+        })
+      )
+      .addSigner(admin)
+      .addSigner(lpPositionMint)
+      .buildAndExecute();
+
+    [vaultPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault"), whirlpool.toBuffer()],
+      program.programId
+    );
+    [observationPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("observation"), whirlpool.toBuffer()],
+      program.programId
+    );
+    [rewardStatePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("reward_state"), whirlpool.toBuffer()],
+      program.programId
+    );
+    [deadSharesPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("dead_shares"), vaultPda.toBuffer()],
+      program.programId
+    );
+    positionPda = PDAUtil.getPosition(
+      ORCA_WHIRLPOOL_PROGRAM_ID,
+      positionMint.publicKey
+    ).publicKey;
+    positionTokenAccount = await getAssociatedTokenAddress(
+      positionMint.publicKey,
+      vaultPda,
+      true
+    );
+
+    await program.methods
+      .initializeVault(
+        lowerTick,
+        upperTick,
+        { whirlpool: {} },
+        new BN(0),
+        100,
+        500,
+        new BN(150)
+      )
+      .accounts({
+        admin: admin.publicKey,
+        vault: vaultPda,
+        observationState: observationPda,
+        rewardState: rewardStatePda,
+        whirlpool: whirlpool,
+        tokenAMint: tokenAMint,
+        tokenBMint: tokenBMint,
+        tokenAVault: tokenAVault.publicKey,
+        tokenBVault: tokenBVault.publicKey,
+        shareMint: shareMint.publicKey,
+        deadShares: deadSharesPda,
+        treasuryTokenA: treasuryTokenAAccount,
+        treasuryTokenB: treasuryTokenBAccount,
+        tokenAPriceFeed: null,
+        tokenBPriceFeed: null,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        rent: SYSVAR_RENT_PUBKEY,
+      })
+      .signers([admin, tokenAVault, tokenBVault, shareMint])
+      .rpc();
+
+    adminShareAccount = await createAccount(
+      provider.connection,
+      admin,
+      shareMint.publicKey,
+      admin.publicKey
+    );
+    await program.methods
+      .deposit(new BN(1_000_000_000), new BN(1_000_000_000), new BN(0), null)
+      .accounts({
+        vault: vaultPda,
+        user: admin.publicKey,
+        whirlpool: whirlpool,
+        position: null,
+        userTokenA: adminTokenAAccount,
+        userTokenB: adminTokenBAccount,
+        tokenAVault: tokenAVault.publicKey,
+        tokenBVault: tokenBVault.publicKey,
+        shareMint: shareMint.publicKey,
+        deadShares: deadSharesPda,
+        userShare: adminShareAccount,
+        feeRecipient: null,
+        tokenAPriceFeed: null,
+        tokenBPriceFeed: null,
+        observationState: observationPda,
+        rewardState: rewardStatePda,
+        userRewardCheckpoint: rewardCheckpointPda(admin.publicKey),
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([admin])
+      .rpc();
+  });
+
+  it("Opens a position with the deposited tokens", async () => {
+    await program.methods
+      .openPosition()
+      .accounts({
+        whirlpoolProgram: ORCA_WHIRLPOOL_PROGRAM_ID,
+        funder: admin.publicKey,
+        vault: vaultPda,
+        position: positionPda,
+        positionMint: positionMint.publicKey,
+        positionTokenAccount: positionTokenAccount,
+        whirlpool: whirlpool,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        rent: SYSVAR_RENT_PUBKEY,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+      })
+      .signers([admin, positionMint])
+      .rpc();
+
+    // About 95% of each token at a price of 1 in a range of +-1024 ticks
+    await program.methods
+      .addLiquidity(
+        new BN(19_000_000_000),
+        new BN(1_000_000_000),
+        new BN(1_000_000_000)
+      )
+      .accounts({
+        operator: admin.publicKey,
+        whirlpoolProgram: ORCA_WHIRLPOOL_PROGRAM_ID,
+        whirlpool: whirlpool,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        vault: vaultPda,
+        position: positionPda,
+        positionTokenAccount: positionTokenAccount,
+        tokenVaultA: tokenAVault.publicKey,
+        tokenVaultB: tokenBVault.publicKey,
+        poolTokenVaultA: poolTokenVaultA,
+        poolTokenVaultB: poolTokenVaultB,
+        tickArrayLower: tickArray(lowerTick),
+        tickArrayUpper: tickArray(upperTick),
+      })
+      .signers([admin])
+      .rpc();
+
+    const position = await whirlpoolContext.fetcher.getPosition(
+      positionPda,
+      IGNORE_CACHE
+    );
+    expect(position.liquidity.toString()).to.equal("19000000000");
+    const vaultAccount = await program.account.vault.fetch(vaultPda);
+    expect(vaultAccount.position.toBase58()).to.eql(positionPda.toBase58());
+  });
+
+  it("Only compounds while the pool stays near the reference price", async () => {
+    const compound = () =>
+      program.methods
+        .compound()
+        .accounts({
+          caller: admin.publicKey,
+          whirlpoolProgram: ORCA_WHIRLPOOL_PROGRAM_ID,
+          whirlpool: whirlpool,
+          vault: vaultPda,
+          position: positionPda,
+          positionTokenAccount: positionTokenAccount,
+          tokenVaultA: tokenAVault.publicKey,
+          poolTokenVaultA: poolTokenVaultA,
+          tokenVaultB: tokenBVault.publicKey,
+          poolTokenVaultB: poolTokenVaultB,
+          callerTokenA: adminTokenAAccount,
+          callerTokenB: adminTokenBAccount,
+          tickArrayLower: tickArray(lowerTick),
+          tickArrayUpper: tickArray(upperTick),
+          tokenAPriceFeed: null,
+          tokenBPriceFeed: null,
+          observationState: observationPda,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([admin])
+        .rpc();
+
+    // About 2% above the reference tick, twice the allowed deviation
+    await pushPrice(200);
+    try {
+      await compound();
+      expect.fail("Expected compound to fail");
+    } catch (err) {
+      expect(err.toString()).to.include("PriceDeviationTooHigh");
+    }
+
+    await updateReferenceTick();
+    await compound();
+    const vaultAccount = await program.account.vault.fetch(vaultPda);
+    expect(vaultAccount.lastCompoundSlot.toNumber()).to.be.greaterThan(0);
+  });
+
+  it("Values deposits and withdrawals against the vault's own position only", async () => {
+    const withdraw = (position: PublicKey | null) =>
+      program.methods
+        .withdraw(new BN(1_000_000), new BN(0), new BN(0))
+        .accounts({
+          user: admin.publicKey,
+          whirlpoolProgram: ORCA_WHIRLPOOL_PROGRAM_ID,
+          whirlpool: whirlpool,
+          vault: vaultPda,
+          position,
+          positionTokenAccount: null,
+          poolTokenVaultA: null,
+          poolTokenVaultB: null,
+          tickArrayLower: null,
+          tickArrayUpper: null,
+          userTokenA: adminTokenAAccount,
+          userTokenB: adminTokenBAccount,
+          tokenAVault: tokenAVault.publicKey,
+          tokenBVault: tokenBVault.publicKey,
+          shareMint: shareMint.publicKey,
+          userShare: adminShareAccount,
+          feeRecipient: null,
+          treasuryTokenA: treasuryTokenAAccount,
+          treasuryTokenB: treasuryTokenBAccount,
+          tokenAPriceFeed: null,
+          tokenBPriceFeed: null,
+          observationState: observationPda,
+          rewardState: rewardStatePda,
+          userRewardCheckpoint: rewardCheckpointPda(admin.publicKey),
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([admin])
+        .rpc();
+
+    // Leaving the position out would value the vault on its idle balances
+    try {
+      await program.methods
+        .deposit(new BN(1_000_000), new BN(1_000_000), new BN(0), null)
+        .accounts({
+          vault: vaultPda,
+          user: admin.publicKey,
+          whirlpool: whirlpool,
+          position: null,
+          userTokenA: adminTokenAAccount,
+          userTokenB: adminTokenBAccount,
+          tokenAVault: tokenAVault.publicKey,
+          tokenBVault: tokenBVault.publicKey,
+          shareMint: shareMint.publicKey,
+          deadShares: deadSharesPda,
+          userShare: adminShareAccount,
+          feeRecipient: null,
+          tokenAPriceFeed: null,
+          tokenBPriceFeed: null,
+          observationState: observationPda,
+          rewardState: rewardStatePda,
+          userRewardCheckpoint: rewardCheckpointPda(admin.publicKey),
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([admin])
+        .rpc();
+      expect.fail("Expected deposit without the position to fail");
+    } catch (err) {
+      expect(err.toString()).to.include("MissingPosition");
+    }
+    try {
+      await withdraw(null);
+      expect.fail("Expected withdraw without the position to fail");
+    } catch (err) {
+      expect(err.toString()).to.include("MissingPosition");
+    }
+
+    // Someone else's larger position on the pool cannot stand in for it
+    try {
+      await withdraw(lpPosition);
+      expect.fail("Expected withdraw with another position to fail");
+    } catch (err) {
+      expect(err.toString()).to.include("ConstraintAddress");
+    }
+  });
+
+  it("Pays single-token withdrawals in full after the exit fee", async () => {
+    const updateFeeSchedule = (withdrawFeeBps: number) =>
+      program.methods
+        .updateFeeSchedule(0, withdrawFeeBps)
+        .accounts({
+          admin: admin.publicKey,
+          whirlpool: whirlpool,
+          vault: vaultPda,
+        })
+        .signers([admin])
+        .rpc();
+    const balance = async (account: PublicKey) =>
+      new BN((await provider.connection.getTokenAccountBalance(account)).value.amount);
+
+    await updateFeeSchedule(100);
+    try {
+      const shares = (await balance(adminShareAccount)).divn(100);
+      const vaultABefore = await balance(tokenAVault.publicKey);
+      const treasuryABefore = await balance(treasuryTokenAAccount);
+      // Token B is redeemed too and swapped into A
+      const tickArrays = await swapTickArrays(false);
+
+      await program.methods
+        .withdrawSingle(shares, new BN(0))
+        .accounts({
+          user: admin.publicKey,
+          whirlpoolProgram: ORCA_WHIRLPOOL_PROGRAM_ID,
+          whirlpool: whirlpool,
+          vault: vaultPda,
+          position: positionPda,
+          userToken: adminTokenAAccount,
+          tokenAVault: tokenAVault.publicKey,
+          tokenBVault: tokenBVault.publicKey,
+          poolTokenVaultA: poolTokenVaultA,
+          poolTokenVaultB: poolTokenVaultB,
+          tickArray0: tickArrays[0],
+          tickArray1: tickArrays[1],
+          tickArray2: tickArrays[2],
+          oracle: oracle(),
+          shareMint: shareMint.publicKey,
+          userShare: adminShareAccount,
+          feeRecipient: null,
+          treasuryTokenA: treasuryTokenAAccount,
+          treasuryTokenB: treasuryTokenBAccount,
+          tokenAPriceFeed: null,
+          tokenBPriceFeed: null,
+          observationState: observationPda,
+          rewardState: rewardStatePda,
+          userRewardCheckpoint: rewardCheckpointPda(admin.publicKey),
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([admin])
+        .rpc();
+
+      // Everything the swap returned went to the user, so the vault's token A
+      // dropped by exactly the redeemed amount the fee was charged on
+      const vaultADecrease = vaultABefore.sub(await balance(tokenAVault.publicKey));
+      const treasuryAIncrease = (await balance(treasuryTokenAAccount)).sub(treasuryABefore);
+      expect(treasuryAIncrease.toNumber()).to.be.greaterThan(0);
+      expect(treasuryAIncrease.toString()).to.equal(
+        vaultADecrease.muln(100).addn(9_999).divn(10_000).toString()
+      );
+    } finally {
+      await updateFeeSchedule(0);
+    }
+  });
+
+  it("Rebalances into a new range with most of the vault value deployed", async () => {
+    const targetWidth = 32;
+    await program.methods
+      .updateRebalancePolicy(200, targetWidth, 0, new BN(0))
+      .accounts({
+        admin: admin.publicKey,
+        whirlpool: whirlpool,
+        vault: vaultPda,
+      })
+      .signers([admin])
+      .rpc();
+
+    // Out of range above, the position now holds only token B
+    await pushPrice(2048);
+    await updateReferenceTick();
+
+    const pool = await whirlpoolContext.fetcher.getPool(
+      whirlpool,
+      IGNORE_CACHE
+    );
+    const width = targetWidth * tickSpacing;
+    const newLowerTick =
+      Math.floor((pool.tickCurrentIndex - width / 2) / tickSpacing) *
+      tickSpacing;
+    const newUpperTick = newLowerTick + width;
+    const newPositionMint = Keypair.generate();
+    const newPositionPda = PDAUtil.getPosition(
+      ORCA_WHIRLPOOL_PROGRAM_ID,
+      newPositionMint.publicKey
+    ).publicKey;
+    // The idle balances are mostly token B and get swapped towards A
+    const tickArrays = await swapTickArrays(false);
+
+    await program.methods
+      .rebalance()
+      .accounts({
+        caller: admin.publicKey,
+        whirlpoolProgram: ORCA_WHIRLPOOL_PROGRAM_ID,
+        whirlpool: whirlpool,
+        vault: vaultPda,
+        position: positionPda,
+        positionMint: positionMint.publicKey,
+        positionTokenAccount: positionTokenAccount,
+        tickArrayLower: tickArray(lowerTick),
+        tickArrayUpper: tickArray(upperTick),
+        newPosition: newPositionPda,
+        newPositionMint: newPositionMint.publicKey,
+        newPositionTokenAccount: await getAssociatedTokenAddress(
+          newPositionMint.publicKey,
+          vaultPda,
+          true
+        ),
+        newTickArrayLower: tickArray(newLowerTick),
+        newTickArrayUpper: tickArray(newUpperTick),
+        tokenVaultA: tokenAVault.publicKey,
+        poolTokenVaultA: poolTokenVaultA,
+        tokenVaultB: tokenBVault.publicKey,
+        poolTokenVaultB: poolTokenVaultB,
+        tickArray0: tickArrays[0],
+        tickArray1: tickArrays[1],
+        tickArray2: tickArrays[2],
+        oracle: oracle(),
+        tokenAPriceFeed: null,
+        tokenBPriceFeed: null,
+        observationState: observationPda,
+        rewardLedger: PublicKey.findProgramAddressSync(
+          [Buffer.from("reward_ledger"), whirlpool.toBuffer()],
+          program.programId
+        )[0],
+        rewardState: rewardStatePda,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        rent: SYSVAR_RENT_PUBKEY,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+      })
+      .signers([admin, newPositionMint])
+      .rpc();
+
+    const vaultAccount = await program.account.vault.fetch(vaultPda);
+    expect(vaultAccount.lowerTick).to.equal(newLowerTick);
+    expect(vaultAccount.upperTick).to.equal(newUpperTick);
+
+    const poolAfter = await whirlpoolContext.fetcher.getPool(
+      whirlpool,
+      IGNORE_CACHE
+    );
+    const position = await whirlpoolContext.fetcher.getPosition(
+      newPositionPda,
+      IGNORE_CACHE
+    );
+    const deployed = PoolUtil.getTokenAmountsFromLiquidity(
+      position.liquidity,
+      poolAfter.sqrtPrice,
+      PriceMath.tickIndexToSqrtPriceX64(newLowerTick),
+      PriceMath.tickIndexToSqrtPriceX64(newUpperTick),
+      false
+    );
+    const idleA = (
+      await provider.connection.getTokenAccountBalance(tokenAVault.publicKey)
+    ).value.amount;
+    const idleB = (
+      await provider.connection.getTokenAccountBalance(tokenBVault.publicKey)
+    ).value.amount;
+    const price = PriceMath.sqrtPriceX64ToPrice(poolAfter.sqrtPrice, 6, 6);
+    const valueInB = (a: string, b: string) =>
+      new Decimal(a).mul(price).add(new Decimal(b));
+    const idleValue = valueInB(idleA, idleB);
+    const deployedValue = valueInB(
+      deployed.tokenA.toString(),
+      deployed.tokenB.toString()
+    );
+    // Without the swap, only the idle token A could be paired and most of
+    // the token B would stay in the vault
+    expect(
+      idleValue.div(idleValue.add(deployedValue)).toNumber()
+    ).to.be.lessThan(0.05);
+  });
+});